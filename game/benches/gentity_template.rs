@@ -0,0 +1,108 @@
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+use bevy::scene::ScenePlugin;
+use bevy_xpbd_3d::prelude::*;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use untitled::gentity::gltf::hook::{processs_gentity_gltf_scene, GEntityMap, ProcessGEntity};
+use untitled::gentity::gltf::pp_collision::setup_pp_collision;
+use untitled::gentity::template::{GEntityTemplate, GEntityTemplateRegistry};
+
+const INSTANCES: u64 = 200;
+
+/// Spawns a GEntity root with `nodes` processed collider nodes, each with a mesh primitive child.
+fn spawn_processed_gentity(world: &mut World, nodes: usize) -> Entity {
+    let root = world.spawn(SpatialBundle::default()).id();
+    for index in 0..nodes {
+        let node = world.spawn((
+            Name::new(format!("collider.{:03}", index)),
+            TransformBundle::from_transform(Transform::from_xyz(index as f32, 0.0, 0.0)),
+            Collider::cuboid(1.0, 1.0, 1.0),
+        )).set_parent(root).id();
+        world.spawn((
+            Name::new(format!("collider.{:03}.mesh", index)),
+            TransformBundle::default(),
+        )).set_parent(node);
+    }
+    root
+}
+
+/// Builds the scene the glTF loader would produce for [spawn_processed_gentity], before any hook ran.
+fn gltf_scene(nodes: usize) -> Scene {
+    let mut world = World::new();
+    let root = world.spawn(TransformBundle::default()).id();
+    for index in 0..nodes {
+        let node = world.spawn((
+            Name::new(format!("collider.{:03}", index)),
+            TransformBundle::from_transform(Transform::from_xyz(index as f32, 0.0, 0.0)),
+        )).set_parent(root).id();
+        world.spawn((
+            Name::new(format!("collider.{:03}.mesh", index)),
+            TransformBundle::default(),
+        )).set_parent(node);
+    }
+    Scene::new(world)
+}
+
+/// An app spawning GEntity scenes and running the collision hook on them, without the template cache.
+fn gltf_app(nodes: usize) -> (App, Handle<Scene>) {
+    let mut app = App::new();
+    app
+        .add_plugins((MinimalPlugins, AssetPlugin::default(), TransformPlugin, HierarchyPlugin, ScenePlugin))
+        .insert_resource(GEntityMap::new())
+        .add_systems(Startup, setup_pp_collision)
+        .add_systems(Update, processs_gentity_gltf_scene.run_if(any_with_component::<ProcessGEntity>()));
+    let scene = app.world.resource_mut::<Assets<Scene>>().add(gltf_scene(nodes));
+    app.update();
+    (app, scene)
+}
+
+/// Spawns the instances like the toml loader does, then lets the scene spawner and the hooks run.
+fn spawn_gltf(app: &mut App, scene: &Handle<Scene>) {
+    for _ in 0..INSTANCES {
+        app.world.spawn((
+            SceneBundle {
+                scene: scene.clone(),
+                ..default()
+            },
+            ProcessGEntity,
+        ));
+    }
+    // The scene instances are spawned after Update, so the hooks run in the second update.
+    app.update();
+    app.update();
+}
+
+fn instantiate(world: &mut World, template: &GEntityTemplate) {
+    let mut queue = CommandQueue::default();
+    let mut cmds = Commands::new(&mut queue, world);
+    for _ in 0..INSTANCES {
+        let root = cmds.spawn(SpatialBundle::default()).id();
+        template.instantiate(root, &mut cmds);
+    }
+    queue.apply(world);
+}
+
+fn bench_gentity_template(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gentity_template");
+    for nodes in [4, 16, 64] {
+        let mut world = World::new();
+        let root = spawn_processed_gentity(&mut world, nodes);
+        let registry = GEntityTemplateRegistry::default();
+        group.throughput(Throughput::Elements(1));
+        group.bench_with_input(BenchmarkId::new("capture", nodes), &nodes, |b, _| {
+            b.iter(|| GEntityTemplate::capture(&world, &registry, root));
+        });
+        let template = GEntityTemplate::capture(&world, &registry, root);
+        group.throughput(Throughput::Elements(INSTANCES));
+        group.bench_with_input(BenchmarkId::new("instantiate", nodes), &nodes, |b, _| {
+            b.iter_batched_ref(World::new, |world| instantiate(world, &template), criterion::BatchSize::LargeInput);
+        });
+        group.bench_with_input(BenchmarkId::new("gltf_spawn", nodes), &nodes, |b, _| {
+            b.iter_batched_ref(|| gltf_app(nodes), |(app, scene)| spawn_gltf(app, scene), criterion::BatchSize::LargeInput);
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_gentity_template);
+criterion_main!(benches);
@@ -0,0 +1,47 @@
+use bevy::math::DVec3;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use untitled::physics_math::double;
+use untitled::solarsystem::barnes_hut;
+use untitled::solarsystem::nbody::{self, NBody};
+
+/// A sun with `count` asteroids scattered through the inner solar system.
+fn asteroid_belt(count: usize) -> Vec<NBody> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut bodies = vec![NBody {
+        position: DVec3::ZERO,
+        velocity: DVec3::ZERO,
+        mu: double::gravitational_parameter(1.989e30),
+        moves: false,
+    }];
+    bodies.extend((0..count).map(|_| NBody {
+        position: DVec3::new(rng.gen_range(-5.0e11..5.0e11), rng.gen_range(-1.0e10..1.0e10), rng.gen_range(-5.0e11..5.0e11)),
+        velocity: DVec3::ZERO,
+        mu: double::gravitational_parameter(rng.gen_range(1.0e10..1.0e20)),
+        moves: true,
+    }));
+    bodies
+}
+
+fn bench_gravity(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gravity");
+    group.sample_size(20);
+    for count in [100, 1000, 5000] {
+        let bodies = asteroid_belt(count);
+        let mut accelerations = vec![DVec3::ZERO; bodies.len()];
+        group.throughput(Throughput::Elements(bodies.len() as u64));
+        group.bench_with_input(BenchmarkId::new("direct", count), &count, |b, _| {
+            b.iter(|| nbody::direct_accelerations(&bodies, &mut accelerations));
+        });
+        for opening_angle in [0.5, 1.0] {
+            group.bench_with_input(BenchmarkId::new(format!("barnes_hut_{}", opening_angle), count), &count, |b, _| {
+                b.iter(|| barnes_hut::accelerations(&bodies, &mut accelerations, opening_angle));
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_gravity);
+criterion_main!(benches);
@@ -0,0 +1,153 @@
+use bevy::app::{App, Plugin};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use bevy_xpbd_3d::math::Scalar;
+use crate::gentity::gltf::pp_atmosphere::{AtmosphereRequest, GEntityAtmosphere, GEntityAtmosphereRequest, GEntityDoor, VACUUM};
+use crate::gentity::script::runtime::GEntityScripts;
+use crate::player::Player;
+
+/// Below this pressure, in kPa, a volume counts as vacuum.
+pub const VACUUM_PRESSURE: Scalar = 1.0;
+
+pub struct AtmospherePlugin;
+
+impl Plugin for AtmospherePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Update, (
+                apply_atmosphere_requests,
+                equalize_pressure,
+                update_player_atmosphere,
+                share_pressures_with_scripts,
+            ).chain())
+        ;
+    }
+}
+
+/// Whether the player breathes, queried by everything caring about vacuum.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub enum AtmosphereState {
+    Vacuum,
+    InAtmosphere {
+        volume: Entity,
+        pressure: Scalar,
+        o2_fraction: Scalar,
+        temperature: Scalar,
+    },
+}
+
+impl AtmosphereState {
+    pub fn is_vacuum(&self) -> bool {
+        matches!(self, AtmosphereState::Vacuum)
+    }
+}
+
+fn apply_atmosphere_requests(
+    mut requests: EventReader<GEntityAtmosphereRequest>,
+    mut volumes: Query<&mut GEntityAtmosphere>,
+    mut doors: Query<&mut GEntityDoor>,
+) {
+    for request in requests.read() {
+        match &request.request {
+            AtmosphereRequest::SetPressure { volume, pressure } => {
+                let Some(mut atmosphere) = volumes.iter_mut().find(|atmosphere| atmosphere.gentity == request.gentity && atmosphere.name == *volume) else {
+                    warn!("GEntity {:?} has no atmosphere volume '{}'", request.gentity, volume);
+                    continue;
+                };
+                atmosphere.pressure = pressure.max(0.0);
+            }
+            AtmosphereRequest::SetDoor { door, open } => {
+                let Some(mut gentity_door) = doors.iter_mut().find(|gentity_door| gentity_door.gentity == request.gentity && gentity_door.name == *door) else {
+                    warn!("GEntity {:?} has no door '{}'", request.gentity, door);
+                    continue;
+                };
+                gentity_door.open = *open;
+            }
+        }
+    }
+}
+
+/// Moves gas through open doors until the pressure of the connected volumes is equal. Doors to
+/// [VACUUM] vent their volume.
+fn equalize_pressure(
+    time: Res<Time>,
+    doors: Query<(Entity, &GEntityDoor)>,
+    mut volumes: Query<(Entity, &mut GEntityAtmosphere)>,
+    mut reported_doors: Local<HashSet<Entity>>,
+) {
+    let delta_time = time.delta_seconds_f64();
+    let volume_entities = volumes.iter()
+        .map(|(entity, atmosphere)| ((atmosphere.gentity, atmosphere.name.clone()), entity))
+        .collect::<HashMap<_, _>>();
+    for (door_entity, door) in doors.iter().filter(|(_, door)| door.open) {
+        let fraction = 1.0 - (-door.flow * delta_time).exp();
+        let find = |name: &String| volume_entities.get(&(door.gentity, name.clone())).copied();
+        match (find(&door.connects[0]), find(&door.connects[1])) {
+            (Some(first), Some(second)) => {
+                let Ok([(_, first), (_, second)]) = volumes.get_many_mut([first, second]) else {
+                    continue;
+                };
+                let (mut from, mut to) = if first.pressure >= second.pressure { (first, second) } else { (second, first) };
+                // Pressure times volume stands in for the amount of gas, as both sides share a temperature.
+                let equalized = (from.pressure * from.volume + to.pressure * to.volume) / (from.volume + to.volume);
+                let amount = (from.pressure - equalized) * from.volume * fraction;
+                if amount <= 0.0 || to.volume <= 0.0 {
+                    continue;
+                }
+                let to_amount = to.pressure * to.volume;
+                to.o2_fraction = (to.o2_fraction * to_amount + from.o2_fraction * amount) / (to_amount + amount);
+                to.temperature = (to.temperature * to_amount + from.temperature * amount) / (to_amount + amount);
+                to.pressure += amount / to.volume;
+                from.pressure -= amount / from.volume;
+            }
+            (Some(volume), None) | (None, Some(volume)) if door.connects.iter().any(|name| name == VACUUM) => {
+                if let Ok((_, mut atmosphere)) = volumes.get_mut(volume) {
+                    atmosphere.pressure -= atmosphere.pressure * fraction;
+                }
+            }
+            _ => {
+                if reported_doors.insert(door_entity) {
+                    warn!("Door '{}' of {:?} connects unknown volumes {:?}", door.name, door.gentity, door.connects);
+                }
+            }
+        }
+    }
+}
+
+fn update_player_atmosphere(
+    volumes: Query<(Entity, &GEntityAtmosphere, &GlobalTransform)>,
+    players: Query<(Entity, &GlobalTransform, Option<&AtmosphereState>), With<Player>>,
+    mut cmds: Commands,
+) {
+    for (player, player_transform, current_state) in players.iter() {
+        let state = volumes.iter()
+            .find(|(_, _, transform)| GEntityAtmosphere::contains(transform, player_transform.translation()))
+            .filter(|(_, atmosphere, _)| atmosphere.pressure >= VACUUM_PRESSURE)
+            .map_or(AtmosphereState::Vacuum, |(volume, atmosphere, _)| AtmosphereState::InAtmosphere {
+                volume,
+                pressure: atmosphere.pressure,
+                o2_fraction: atmosphere.o2_fraction,
+                temperature: atmosphere.temperature,
+            });
+        if current_state != Some(&state) {
+            cmds.entity(player).insert(state);
+        }
+    }
+}
+
+fn share_pressures_with_scripts(
+    volumes: Query<Ref<GEntityAtmosphere>>,
+    scripts: Query<Ref<GEntityScripts>>,
+) {
+    for atmosphere in volumes.iter() {
+        let Ok(entity_scripts) = scripts.get(atmosphere.gentity) else {
+            continue;
+        };
+        if !atmosphere.is_changed() && !entity_scripts.is_added() {
+            continue;
+        }
+        if let Ok(mut shared_state) = entity_scripts.shared_state().lock() {
+            shared_state.pressures.insert(atmosphere.name.clone(), atmosphere.pressure);
+        }
+    }
+}
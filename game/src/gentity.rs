@@ -1,3 +1,7 @@
 pub(crate) mod asset_loaders;
-pub(crate) mod plugin;
-pub(crate) mod gltf;
+pub mod despawn;
+pub mod plugin;
+pub mod gltf;
+pub(crate) mod script;
+pub(crate) mod state;
+pub mod template;
@@ -1,15 +1,23 @@
 use bevy::app::App;
 use bevy::asset::{Asset, AssetApp, AssetLoader, AsyncReadExt, BoxedFuture, LoadContext};
 use bevy::asset::io::Reader;
+use bevy::gltf::Gltf;
+use bevy::math::DVec3;
 use bevy::prelude::*;
 use bevy::reflect::TypePath;
 use bevy::render::primitives::Aabb;
 use bevy::scene::SceneInstance;
+use bevy::utils::HashMap;
+use bevy_xpbd_3d::prelude::*;
 use thiserror::Error;
 use toml::Table;
 use crate::gentity::asset_loaders::rhai_asset_loader::{RhaiScript};
 use crate::gentity::gltf::hook::{GEntityMap, ProcessGEntity};
+use crate::gentity::gltf::pp_collision::GEntityMassPending;
 use crate::gentity::plugin::GEntityInitializeFromTomlComponent;
+use crate::gentity::state::GEntityState;
+use crate::gentity::template::{GEntityTemplateSource, GEntityTemplateUse, GEntityTemplates};
+use crate::gentity::script::capabilities::{ScriptCapabilities, ScriptCapability};
 use crate::localization::Localization;
 
 
@@ -43,6 +51,64 @@ pub struct TomlAssetDisplay {
     description: String,
 }
 
+/// The kind of [RigidBody] put on the GEntity root, read from `[physics] body`.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TomlAssetBody {
+    Dynamic,
+    #[default]
+    Kinematic,
+    Static,
+}
+
+impl TomlAssetBody {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "dynamic" => Some(Self::Dynamic),
+            "kinematic" => Some(Self::Kinematic),
+            "static" => Some(Self::Static),
+            _ => None,
+        }
+    }
+
+    pub fn rigid_body(&self) -> RigidBody {
+        match self {
+            TomlAssetBody::Dynamic => RigidBody::Dynamic,
+            TomlAssetBody::Kinematic => RigidBody::Kinematic,
+            TomlAssetBody::Static => RigidBody::Static,
+        }
+    }
+}
+
+/// The `[physics]` section of a package.
+#[derive(Default, Debug, Clone)]
+pub struct TomlAssetPhysics {
+    pub body: TomlAssetBody,
+    /// The total mass in kilograms. Takes precedence over `density`.
+    pub mass: Option<f64>,
+    /// The density of all colliders in kilograms per cubic meter.
+    pub density: Option<f64>,
+    pub center_of_mass: Option<DVec3>,
+    pub friction: Option<f64>,
+    pub restitution: Option<f64>,
+    /// The collision layers (0 to 31) the colliders are part of.
+    pub memberships: Option<Vec<u32>>,
+    /// The collision layers (0 to 31) the colliders collide with.
+    pub filters: Option<Vec<u32>>,
+}
+
+impl TomlAssetPhysics {
+    pub fn collision_layers(&self) -> Option<CollisionLayers> {
+        if self.memberships.is_none() && self.filters.is_none() {
+            return None;
+        }
+        let to_bits = |layers: &Option<Vec<u32>>| match layers {
+            Some(layers) => layers.iter().fold(0u32, |bits, layer| bits | (1 << layer)),
+            None => u32::MAX,
+        };
+        Some(CollisionLayers::from_bits(to_bits(&self.memberships), to_bits(&self.filters)))
+    }
+}
+
 #[derive(Default, Asset, TypePath, Debug)]
 pub struct TomlAsset {
     pub identifier: String,
@@ -50,7 +116,10 @@ pub struct TomlAsset {
     pub display: TomlAssetDisplay,
     pub localizations: Vec<TomlAssetLocalization>,
     pub gltf_asset: Handle<Scene>,
+    pub gltf_file_asset: Handle<Gltf>,
     pub script_assets: Vec<Handle<RhaiScript>>,
+    pub capabilities: ScriptCapabilities,
+    pub physics: TomlAssetPhysics,
 }
 
 #[non_exhaustive]
@@ -82,6 +151,16 @@ pub enum TomlAssetLoaderError {
     OneOrMoreLocalizationsFailedToBeReadAsCultureNotFound,
     #[error("Failed creating gltf path")]
     FailedCreatingGltfPath,
+    #[error("Failed reading script capabilities as not an array")]
+    FailedReadingCapabilitiesAsNotAnArray,
+    #[error("Failed reading script capability as not a string")]
+    FailedReadingCapabilityAsNotAString,
+    #[error("Unknown script capability '{0}'")]
+    UnknownCapability(String),
+    #[error("Failed reading physics as not a table")]
+    FailedReadingPhysicsAsNotATable,
+    #[error("Invalid physics value for '{0}'")]
+    InvalidPhysicsValue(String),
 }
 
 
@@ -140,12 +219,30 @@ fn load<'a>(
                 Err(value) => return Err(value),
             };
 
+            let gltf_file_asset = match Self::load_gltf_file_asset(load_context, &base_path, &gltf) {
+                Ok(value) => value,
+                Err(value) => return Err(value),
+            };
+
             // Read all script assets in base_path/scripts
             let script_assets = match Self::load_script_assets(load_context, &base_path) {
                 Ok(value) => value,
                 Err(value) => return Err(value),
             };
 
+            let capabilities = match Self::read_capabilities_from_toml(&table) {
+                Ok(value) => value,
+                Err(value) => return Err(value),
+            };
+            if !script_assets.is_empty() {
+                info!("Package '{}' requests script capabilities: {}", identifier, capabilities);
+            }
+
+            let physics = match Self::read_physics_from_toml(&table) {
+                Ok(value) => value,
+                Err(value) => return Err(value),
+            };
+
             let display = Self::read_display_from_toml(&table);
 
             let localizations = match Self::read_localization_from_toml(table) {
@@ -160,7 +257,10 @@ fn load<'a>(
                 display,
                 localizations,
                 gltf_asset,
+                gltf_file_asset,
                 script_assets,
+                capabilities,
+                physics,
             };
 
             Ok(custom_asset)
@@ -290,6 +390,121 @@ fn load_gltf_assets(load_context: &mut LoadContext, base_path: &String, gltf: &S
         Ok(gltf_asset)
     }
 
+    fn load_gltf_file_asset(load_context: &mut LoadContext, base_path: &String, gltf: &String) -> Result<Handle<Gltf>, TomlAssetLoaderError> {
+        let gltf_path = std::path::Path::new(base_path).join(gltf);
+        let gltf_path = gltf_path.to_str();
+        let Some(gltf_path) = gltf_path else {
+            return Err(TomlAssetLoaderError::FailedCreatingGltfPath);
+        };
+        let gltf_file_asset: Handle<Gltf> = load_context.load(gltf_path.to_string());
+        Ok(gltf_file_asset)
+    }
+
+    fn read_capabilities_from_toml(table: &Table) -> Result<ScriptCapabilities, TomlAssetLoaderError> {
+        let capabilities = match table.get("scripts") {
+            Some(scripts) => match scripts.get("capabilities") {
+                Some(capabilities) => match capabilities.as_array() {
+                    Some(capabilities) => {
+                        let mut capabilities_vec = Vec::new();
+                        for capability in capabilities {
+                            let capability = match capability.as_str() {
+                                Some(capability) => capability,
+                                None => return Err(TomlAssetLoaderError::FailedReadingCapabilityAsNotAString),
+                            };
+                            match ScriptCapability::parse(capability) {
+                                Some(capability) => capabilities_vec.push(capability),
+                                None => return Err(TomlAssetLoaderError::UnknownCapability(capability.to_string())),
+                            }
+                        }
+                        ScriptCapabilities::new(capabilities_vec)
+                    }
+                    None => return Err(TomlAssetLoaderError::FailedReadingCapabilitiesAsNotAnArray),
+                },
+                None => ScriptCapabilities::default(),
+            },
+            None => ScriptCapabilities::default(),
+        };
+        Ok(capabilities)
+    }
+
+    fn read_physics_from_toml(table: &Table) -> Result<TomlAssetPhysics, TomlAssetLoaderError> {
+        let physics = match table.get("physics") {
+            Some(physics) => match physics.as_table() {
+                Some(physics) => physics,
+                None => return Err(TomlAssetLoaderError::FailedReadingPhysicsAsNotATable),
+            },
+            None => return Ok(TomlAssetPhysics::default()),
+        };
+        let body = match physics.get("body") {
+            Some(body) => match body.as_str().and_then(TomlAssetBody::parse) {
+                Some(body) => body,
+                None => return Err(TomlAssetLoaderError::InvalidPhysicsValue("body".into())),
+            },
+            None => TomlAssetBody::default(),
+        };
+        let mass = Self::read_physics_number(physics, "mass", |mass| mass > 0.0)?;
+        let density = Self::read_physics_number(physics, "density", |density| density > 0.0)?;
+        let friction = Self::read_physics_number(physics, "friction", |friction| friction >= 0.0)?;
+        let restitution = Self::read_physics_number(physics, "restitution", |restitution| (0.0..=1.0).contains(&restitution))?;
+        let center_of_mass = match physics.get("center_of_mass") {
+            Some(center_of_mass) => match center_of_mass.as_array() {
+                Some(values) if values.len() == 3 => {
+                    let values = values.iter().filter_map(Self::as_number).collect::<Vec<_>>();
+                    if values.len() != 3 {
+                        return Err(TomlAssetLoaderError::InvalidPhysicsValue("center_of_mass".into()));
+                    }
+                    Some(DVec3::new(values[0], values[1], values[2]))
+                }
+                _ => return Err(TomlAssetLoaderError::InvalidPhysicsValue("center_of_mass".into())),
+            },
+            None => None,
+        };
+        let memberships = Self::read_physics_layers(physics, "memberships")?;
+        let filters = Self::read_physics_layers(physics, "filters")?;
+        Ok(TomlAssetPhysics {
+            body,
+            mass,
+            density,
+            center_of_mass,
+            friction,
+            restitution,
+            memberships,
+            filters,
+        })
+    }
+
+    fn as_number(value: &toml::Value) -> Option<f64> {
+        value.as_float().or_else(|| value.as_integer().map(|value| value as f64))
+    }
+
+    fn read_physics_number(physics: &Table, key: &str, is_valid: impl Fn(f64) -> bool) -> Result<Option<f64>, TomlAssetLoaderError> {
+        match physics.get(key) {
+            Some(value) => match Self::as_number(value) {
+                Some(value) if is_valid(value) => Ok(Some(value)),
+                _ => Err(TomlAssetLoaderError::InvalidPhysicsValue(key.into())),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn read_physics_layers(physics: &Table, key: &str) -> Result<Option<Vec<u32>>, TomlAssetLoaderError> {
+        let layers = match physics.get(key) {
+            Some(layers) => match layers.as_array() {
+                Some(layers) => layers,
+                None => return Err(TomlAssetLoaderError::InvalidPhysicsValue(key.into())),
+            },
+            None => return Ok(None),
+        };
+        let mut layers_vec = Vec::new();
+        for layer in layers {
+            match layer.as_integer() {
+                Some(layer) if (0..32).contains(&layer) => layers_vec.push(layer as u32),
+                _ => return Err(TomlAssetLoaderError::InvalidPhysicsValue(key.into())),
+            }
+        }
+        Ok(Some(layers_vec))
+    }
+
     fn read_gltf_from_toml(table: &Table) -> Result<String, TomlAssetLoaderError> {
         let gltf = match table.get("gltf") {
             Some(gltf) => match gltf.as_str() {
@@ -317,20 +532,28 @@ pub fn toml_asset_changed(
     mut event_reader: EventReader<AssetEvent<TomlAsset>>,
     mut assets: ResMut<Assets<TomlAsset>>,
     mut localization: ResMut<Localization>,
+    mut localized_keys: Local<HashMap<AssetId<TomlAsset>, Vec<(String, String)>>>,
 ) {
     for ev in event_reader.read() {
         match ev {
             AssetEvent::Added { .. } => {}
             AssetEvent::Modified { .. } => {}
-            AssetEvent::Removed { .. } => {}
+            AssetEvent::Removed { id } => {
+                // Unloaded packages take their localizations with them.
+                for (culture, key) in localized_keys.remove(id).unwrap_or_default() {
+                    localization.remove(&culture, &key);
+                }
+            }
             AssetEvent::LoadedWithDependencies { id } => {
                 let opt = assets.get_mut(*id);
                 let Some(toml_asset) = opt else {
                     panic!("TOML asset should be loaded at this point but it isn't");
                 };
+                let keys = localized_keys.entry(*id).or_default();
                 for asset_localization in &toml_asset.localizations {
                     for entry in &asset_localization.entries {
                         localization.set(&asset_localization.culture, entry.key.clone(), entry.value.clone());
+                        keys.push((asset_localization.culture.clone(), entry.key.clone()));
                     }
                 }
             }
@@ -340,23 +563,62 @@ pub fn toml_asset_changed(
 
 pub fn process_gentity_toml_file(
     unloaded_instances: Query<(Entity, &Transform, &Handle<TomlAsset>), With<GEntityInitializeFromTomlComponent>>,
+    states: Query<&GEntityState>,
     assets: Res<Assets<TomlAsset>>,
+    mut templates: ResMut<GEntityTemplates>,
     mut cmds: Commands,
 ) {
     for (entity, transform, toml_asset_handle) in unloaded_instances.iter() {
         if let Some(asset) = assets.get(toml_asset_handle) {
-            cmds
-                .entity(entity)
-                .remove::<GEntityInitializeFromTomlComponent>()
-                .remove::<Transform>()
-                .insert(SceneBundle {
-                    transform: transform.clone(),
-                    scene: asset.gltf_asset.clone(),
-                    ..default()
-                })
-                .insert(ProcessGEntity)
-            ;
-            // ToDo: Handle rhai scripts
+            let template_use = templates.reserve(toml_asset_handle.id(), entity, |source| {
+                matches!(states.get(source), Ok(state) if !matches!(state, GEntityState::Failed(_)))
+            });
+            let physics = &asset.physics;
+            let mut entity_cmds = cmds.entity(entity);
+            match &template_use {
+                GEntityTemplateUse::Wait => continue,
+                GEntityTemplateUse::Capture => {
+                    entity_cmds
+                        .remove::<GEntityInitializeFromTomlComponent>()
+                        .remove::<Transform>()
+                        .insert(SceneBundle {
+                            transform: transform.clone(),
+                            scene: asset.gltf_asset.clone(),
+                            ..default()
+                        })
+                        .insert(ProcessGEntity)
+                        .insert(GEntityTemplateSource)
+                        .insert(GEntityState::Instantiating)
+                    ;
+                }
+                GEntityTemplateUse::Instantiate(_) => {
+                    entity_cmds
+                        .remove::<GEntityInitializeFromTomlComponent>()
+                        .remove::<Transform>()
+                        .insert(SpatialBundle {
+                            transform: transform.clone(),
+                            ..default()
+                        })
+                        .insert(GEntityState::ProcessingHooks)
+                    ;
+                }
+            }
+            entity_cmds.insert(physics.body.rigid_body());
+            if let Some(friction) = physics.friction {
+                entity_cmds.insert(Friction::new(friction));
+            }
+            if let Some(restitution) = physics.restitution {
+                entity_cmds.insert(Restitution::new(restitution));
+            }
+            if let Some(collision_layers) = physics.collision_layers() {
+                entity_cmds.insert(collision_layers);
+            }
+            if physics.mass.is_some() || physics.center_of_mass.is_some() {
+                entity_cmds.insert(GEntityMassPending);
+            }
+            if let GEntityTemplateUse::Instantiate(template) = template_use {
+                template.instantiate(entity, &mut cmds);
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,171 @@
+use bevy::prelude::*;
+use bevy::scene::SceneInstance;
+use bevy::utils::HashSet;
+use crate::gentity::asset_loaders::toml_asset_loader::TomlAsset;
+use crate::gentity::gltf::pp_dock::{GEntityDocking, GEntityDockingPort, GEntityUndocked};
+use crate::gentity::script::runtime::{GEntityScriptCall, GEntityScripts};
+use crate::gentity::template::GEntityTemplates;
+
+/// Despawns a GEntity with everything it spawned. Its scripts get a last `onDespawn()` call.
+#[derive(Event, Clone, Copy)]
+pub struct DespawnGEntity {
+    pub entity: Entity,
+}
+
+#[derive(Event)]
+pub struct GEntityDespawned {
+    pub entity: Entity,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn despawn_gentities(
+    mut requests: EventReader<DespawnGEntity>,
+    instances: Query<Option<&SceneInstance>>,
+    packages: Query<(Entity, &Handle<TomlAsset>)>,
+    children: Query<&Children>,
+    mut scripts: Query<&mut GEntityScripts>,
+    dockings: Query<(Entity, &GEntityDocking)>,
+    mut ports: Query<&mut GEntityDockingPort>,
+    mut scene_spawner: Option<ResMut<SceneSpawner>>,
+    mut templates: ResMut<GEntityTemplates>,
+    mut despawned_events: EventWriter<GEntityDespawned>,
+    mut undocked_events: EventWriter<GEntityUndocked>,
+    mut script_calls: EventWriter<GEntityScriptCall>,
+    mut cmds: Commands,
+) {
+    let mut despawned = HashSet::new();
+    for request in requests.read() {
+        let entity = request.entity;
+        let Ok(instance) = instances.get(entity) else {
+            continue;
+        };
+        if !despawned.insert(entity) {
+            continue;
+        }
+
+        // Scripts run a last time, whatever they ask for afterwards is dropped with them.
+        if let Ok(mut entity_scripts) = scripts.get_mut(entity) {
+            if let Err(error) = entity_scripts.call("onDespawn", vec![]) {
+                error!("{}", error);
+            }
+        }
+
+        // Docking joints live outside of the hierarchy, release the other side.
+        for (joint, docking) in dockings.iter() {
+            let Ok([port_1, port_2]) = ports.get_many_mut([docking.port_1, docking.port_2]) else {
+                continue;
+            };
+            let (mut own, mut other) = match (port_1.gentity == entity, port_2.gentity == entity) {
+                (true, _) => (port_1, port_2),
+                (_, true) => (port_2, port_1),
+                _ => continue,
+            };
+            own.docked_with = None;
+            other.docked_with = None;
+            cmds.entity(joint).despawn();
+            undocked_events.send(GEntityUndocked {
+                port_1: docking.port_1,
+                port_2: docking.port_2,
+            });
+            script_calls.send(GEntityScriptCall::new(other.gentity, "onUndocked", vec![other.name.clone().into(), own.name.clone().into()]));
+        }
+
+        // The scene spawner despawns the entities of the scene instance, everything else below the
+        // root (template instances, placeholders) is despawned here.
+        let mut instance_entities = HashSet::new();
+        if let (Some(instance), Some(scene_spawner)) = (instance, scene_spawner.as_mut()) {
+            instance_entities.extend(scene_spawner.iter_instance_entities(**instance));
+            scene_spawner.despawn_instance(**instance);
+        }
+        for descendant in children.iter_descendants(entity) {
+            if !instance_entities.contains(&descendant) {
+                cmds.entity(descendant).despawn();
+            }
+        }
+        cmds.entity(entity).despawn();
+
+        // Dropping the template of the last GEntity of a package releases the handles it holds, so
+        // the package assets and their localizations get unloaded.
+        if let Ok((_, handle)) = packages.get(entity) {
+            let in_use = packages.iter().any(|(other, other_handle)| !despawned.contains(&other) && other_handle.id() == handle.id());
+            if !in_use {
+                templates.invalidate(handle.id());
+            }
+        }
+        despawned_events.send(GEntityDespawned { entity });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+    use bevy::ecs::system::{CommandQueue, RunSystemOnce};
+    use bevy_xpbd_3d::prelude::*;
+    use crate::gentity::gltf::hook::GEntityMap;
+    use crate::gentity::gltf::pp_dock::{dock_aligned_ports, setup_pp_dock, GEntityDocked};
+    use crate::gentity::gltf::pp_trigger::{setup_pp_trigger, GEntityTrigger};
+    use crate::gentity::script::runtime::GEntityNotification;
+    use crate::gentity::template::{GEntityTemplate, GEntityTemplateRegistry};
+    use super::*;
+
+    /// Captures a processed GEntity with a trigger node and a docking port at its origin, facing -Z.
+    fn docking_template() -> GEntityTemplate {
+        let mut world = World::new();
+        world.insert_resource(GEntityMap::new());
+        world.init_resource::<GEntityTemplateRegistry>();
+        world.run_system_once(setup_pp_trigger);
+        world.run_system_once(setup_pp_dock);
+        let gentity = world.spawn(TransformBundle::default()).id();
+        let trigger = world.spawn((
+            Name::new("trigger.door"),
+            TransformBundle::default(),
+            Collider::cuboid(1.0, 1.0, 1.0),
+            Sensor,
+            GEntityTrigger { gentity, name: "door".into() },
+        )).set_parent(gentity).id();
+        world.spawn(TransformBundle::default()).set_parent(trigger);
+        world.spawn((
+            Name::new("dock.front"),
+            TransformBundle::default(),
+            GEntityDockingPort::new(gentity, "front"),
+        )).set_parent(gentity);
+        GEntityTemplate::capture(&world, world.resource::<GEntityTemplateRegistry>(), gentity)
+    }
+
+    #[test]
+    fn despawn_gentity_leaks_no_entities() {
+        let mut world = World::new();
+        world.init_resource::<Events<DespawnGEntity>>();
+        world.init_resource::<Events<GEntityDespawned>>();
+        world.init_resource::<Events<GEntityDocked>>();
+        world.init_resource::<Events<GEntityUndocked>>();
+        world.init_resource::<Events<GEntityScriptCall>>();
+        world.init_resource::<Events<GEntityNotification>>();
+        world.init_resource::<GEntityTemplates>();
+        // Another GEntity with a port half a meter in front of the port of the template, facing it.
+        let bystander = world.spawn(TransformBundle::default()).id();
+        let bystander_port = world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0.0, 0.0, -0.5).with_rotation(Quat::from_rotation_y(PI))),
+            GEntityDockingPort::new(bystander, "back"),
+        )).set_parent(bystander).id();
+        let baseline = world.entities().len();
+
+        let gentity = world.spawn((Handle::<TomlAsset>::default(), TransformBundle::default())).id();
+        let mut queue = CommandQueue::default();
+        docking_template().instantiate(gentity, &mut Commands::new(&mut queue, &world));
+        queue.apply(&mut world);
+        world.run_system_once(dock_aligned_ports);
+        let joints = world.query_filtered::<Entity, (With<FixedJoint>, With<GEntityDocking>)>().iter(&world).collect::<Vec<_>>();
+        assert_eq!(joints.len(), 1);
+        assert!(world.get::<Parent>(joints[0]).is_none());
+        assert!(world.get::<GEntityDockingPort>(bystander_port).unwrap().docked_with.is_some());
+
+        world.send_event(DespawnGEntity { entity: gentity });
+        world.run_system_once(despawn_gentities);
+
+        assert_eq!(world.entities().len(), baseline);
+        assert!(world.get::<GEntityDockingPort>(bystander_port).unwrap().docked_with.is_none());
+        assert_eq!(world.resource::<Events<GEntityDespawned>>().len(), 1);
+        assert_eq!(world.resource::<Events<GEntityUndocked>>().len(), 1);
+    }
+}
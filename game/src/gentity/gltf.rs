@@ -1,3 +1,12 @@
-pub(crate) mod pp_collision;
-pub(crate) mod hook;
-pub(crate) mod pp_trigger;
\ No newline at end of file
+pub mod pp_collision;
+pub mod hook;
+pub(crate) mod pp_trigger;
+pub(crate) mod shape;
+pub(crate) mod pp_spawn;
+pub(crate) mod pp_seat;
+pub(crate) mod pp_interact;
+pub(crate) mod pp_thruster;
+pub(crate) mod pp_dock;
+pub(crate) mod pp_gravity;
+pub(crate) mod pp_atmosphere;
+pub(crate) mod pp_joint;
@@ -1,18 +1,276 @@
 use bevy::core::Name;
+use bevy::gltf::GltfExtras;
 use bevy::prelude::*;
 use bevy::render::primitives::Aabb;
 use bevy::scene::SceneInstance;
-use bevy_xpbd_3d::prelude::RigidBody;
-use crate::bevy_stupid::debug_print_components_to_console;
+use regex::Regex;
+use thiserror::Error;
+use crate::gentity::asset_loaders::toml_asset_loader::TomlAsset;
+use crate::gentity::state::GEntityState;
 
 #[derive(Default, Component)]
 pub struct ProcessGEntity;
 
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum GEntityHookError {
+    #[error("Node {0:?} has no {1} component")]
+    MissingComponent(Entity, &'static str),
+    #[error("Failed parsing gltf extras: {0}")]
+    InvalidExtras(String),
+    #[error("Invalid gltf extras value for '{key}': {reason}")]
+    InvalidExtrasValue { key: String, reason: String },
+    #[error("{0}")]
+    Other(String),
+}
+
+/// A failed [GEntityHook] call, collected in [GEntityHookFailures] on the GEntity it happened in.
+#[derive(Debug)]
+pub struct GEntityHookFailure {
+    pub hook: &'static str,
+    pub node: Entity,
+    pub node_name: String,
+    pub error: GEntityHookError,
+}
+
+#[derive(Default, Debug, Component)]
+pub struct GEntityHookFailures(pub Vec<GEntityHookFailure>);
+
+/// The custom properties of a gltf node (Blender exports them as [GltfExtras]), parsed as a JSON object.
+#[derive(Default, Debug, Clone)]
+pub struct GEntityNodeExtras(serde_json::Map<String, serde_json::Value>);
+
+impl GEntityNodeExtras {
+    pub fn parse(extras: Option<&GltfExtras>) -> Result<Self, GEntityHookError> {
+        let Some(extras) = extras else {
+            return Ok(Self::default());
+        };
+        match serde_json::from_str::<serde_json::Value>(&extras.value) {
+            Ok(serde_json::Value::Object(map)) => Ok(Self(map)),
+            Ok(_) => Err(GEntityHookError::InvalidExtras("not a JSON object".into())),
+            Err(error) => Err(GEntityHookError::InvalidExtras(error.to_string())),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&serde_json::Value> {
+        self.0.get(key)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.as_str())
+    }
+
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        self.0.get(key).and_then(|value| value.as_f64())
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.0.get(key).and_then(|value| value.as_bool())
+    }
+
+    /// Reads the number `key`, `None` if it is missing. Fails if it is not a finite number or
+    /// `is_valid` rejects it.
+    pub fn get_number(&self, key: &str, is_valid: fn(f64) -> bool) -> Result<Option<f64>, GEntityHookError> {
+        let Some(value) = self.0.get(key) else {
+            return Ok(None);
+        };
+        let reason = match value.as_f64() {
+            Some(number) if number.is_finite() && is_valid(number) => return Ok(Some(number)),
+            Some(number) if number.is_finite() => format!("{} is out of range", value),
+            _ => format!("{} is not a number", value),
+        };
+        Err(GEntityHookError::InvalidExtrasValue { key: key.into(), reason })
+    }
+
+    /// Reads the number `key` like [GEntityNodeExtras::get_number], `default` if it is missing.
+    pub fn number(&self, key: &str, default: f64, is_valid: fn(f64) -> bool) -> Result<f64, GEntityHookError> {
+        Ok(self.get_number(key, is_valid)?.unwrap_or(default))
+    }
+}
+
+/// Everything a [GEntityHook] gets to see about the node it was matched on.
+pub struct GEntityHookContext<'w> {
+    pub world: &'w World,
+    /// The GEntity root, carrying the [SceneInstance] and the [Handle<TomlAsset>].
+    pub gentity: EntityRef<'w>,
+    pub node: EntityRef<'w>,
+    /// The node name with the matched prefix stripped.
+    pub name: &'w str,
+    pub local_transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub extras: GEntityNodeExtras,
+    pub toml: Option<&'w Handle<TomlAsset>>,
+}
+
+impl<'w> GEntityHookContext<'w> {
+    pub fn toml_asset(&self) -> Option<&'w TomlAsset> {
+        let toml = self.toml?;
+        self.world.resource::<Assets<TomlAsset>>().get(toml)
+    }
+
+    pub fn node_transform(&self) -> Result<&'w Transform, GEntityHookError> {
+        self.node.get::<Transform>().ok_or(GEntityHookError::MissingComponent(self.node.id(), "Transform"))
+    }
+
+    /// Combines the local transforms from `entity` up to the GEntity root. Unlike [GlobalTransform]s,
+    /// these are valid while the scene is still being processed.
+    pub fn transform_in_gentity(&self, entity: Entity) -> Result<Transform, GEntityHookError> {
+        let mut transform = Transform::IDENTITY;
+        let mut current = entity;
+        while current != self.gentity.id() {
+            let entity_ref = self.world.get_entity(current).ok_or(GEntityHookError::MissingComponent(current, "Transform"))?;
+            let local = entity_ref.get::<Transform>().ok_or(GEntityHookError::MissingComponent(current, "Transform"))?;
+            transform = local.mul_transform(transform);
+            current = entity_ref.get::<Parent>().ok_or(GEntityHookError::MissingComponent(current, "Parent"))?.get();
+        }
+        Ok(transform)
+    }
+
+    /// Finds the node named `name` (ignoring Blender's `.001` suffixes) in the scene of the GEntity.
+    pub fn find_node(&self, name: &str) -> Option<EntityRef<'w>> {
+        let mut pending = vec![self.gentity.id()];
+        while let Some(entity) = pending.pop() {
+            let Some(entity_ref) = self.world.get_entity(entity) else {
+                continue;
+            };
+            if entity_ref.get::<Name>().is_some_and(|node_name| strip_blender_suffix(node_name.as_str()) == name) {
+                return Some(entity_ref);
+            }
+            if let Some(children) = entity_ref.get::<Children>() {
+                pending.extend(children.iter().copied());
+            }
+        }
+        None
+    }
+}
+
+/// Post-processing for gltf nodes of a GEntity scene, registered in the [GEntityMap].
+pub trait GEntityHook: Send + Sync + 'static {
+    fn run(&self, context: &GEntityHookContext, cmds: &mut Commands) -> Result<(), GEntityHookError>;
+
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// Selects the gltf nodes a [GEntityHook] runs on.
+pub enum GEntityMatcher {
+    /// Matches node names starting with the prefix, the hook gets the name with the prefix and
+    /// Blender's `.001` suffixes stripped.
+    Prefix(String),
+    /// Matches node names against a glob pattern (`*` and `?`), ignoring Blender's `.001` suffixes.
+    Glob(Regex),
+    /// Matches node names against a regular expression, ignoring Blender's `.001` suffixes.
+    Regex(Regex),
+    /// Matches nodes whose gltf extras contain `key` with the string value `value`.
+    Extras { key: String, value: String },
+}
+
+impl GEntityMatcher {
+    pub fn prefix(prefix: impl Into<String>) -> Self {
+        Self::Prefix(prefix.into())
+    }
+
+    pub fn glob(pattern: &str) -> Result<Self, regex::Error> {
+        let mut expression = String::from("^");
+        for c in pattern.chars() {
+            match c {
+                '*' => expression.push_str(".*"),
+                '?' => expression.push('.'),
+                c => expression.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        expression.push('$');
+        Ok(Self::Glob(Regex::new(&expression)?))
+    }
+
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self::Regex(Regex::new(pattern)?))
+    }
+
+    pub fn extras(key: impl Into<String>, value: impl Into<String>) -> Self {
+        Self::Extras {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+
+    /// Returns the name handed to the hook if the node matches.
+    pub fn matches<'a>(&self, name: &'a str, extras: &GEntityNodeExtras) -> Option<&'a str> {
+        match self {
+            GEntityMatcher::Prefix(prefix) => {
+                if name.len() > prefix.len() && name.starts_with(prefix.as_str()) {
+                    Some(strip_blender_suffix(&name[prefix.len()..]))
+                } else {
+                    None
+                }
+            }
+            GEntityMatcher::Glob(regex) | GEntityMatcher::Regex(regex) => {
+                let name = strip_blender_suffix(name);
+                regex.is_match(name).then_some(name)
+            }
+            GEntityMatcher::Extras { key, value } => {
+                (extras.get_str(key) == Some(value.as_str())).then_some(strip_blender_suffix(name))
+            }
+        }
+    }
+}
+
+/// Strips the `.001` style suffix Blender appends to duplicated object names.
+pub fn strip_blender_suffix(name: &str) -> &str {
+    let bytes = name.as_bytes();
+    if bytes.len() > 4 && bytes[bytes.len() - 4] == b'.' && bytes[bytes.len() - 3..].iter().all(|b| b.is_ascii_digit()) {
+        &name[..name.len() - 4]
+    } else {
+        name
+    }
+}
 
-struct GEntityMapEntry {
-    prefix: String,
+pub struct GEntityMapEntry {
+    matchers: Vec<GEntityMatcher>,
+    priority: i32,
+    stop_after_match: bool,
     keep_mesh_render: bool,
-    hook: Box<dyn Fn(EntityRef, EntityRef, &mut Commands) + Send + Sync + 'static>,
+    hook: Box<dyn GEntityHook>,
+}
+
+impl GEntityMapEntry {
+    pub fn new(hook: impl GEntityHook) -> Self {
+        Self {
+            matchers: vec![],
+            priority: 0,
+            stop_after_match: false,
+            keep_mesh_render: false,
+            hook: Box::new(hook),
+        }
+    }
+
+    /// Adds a matcher, the entry runs if any of its matchers matches.
+    pub fn matching(mut self, matcher: GEntityMatcher) -> Self {
+        self.matchers.push(matcher);
+        self
+    }
+
+    /// Entries with a higher priority run first. Entries with equal priority run in registration order.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// No further entries run on a node once this entry matched it.
+    pub fn stop_after_match(mut self) -> Self {
+        self.stop_after_match = true;
+        self
+    }
+
+    pub fn keep_mesh_render(mut self, keep_mesh_render: bool) -> Self {
+        self.keep_mesh_render = keep_mesh_render;
+        self
+    }
+
+    fn matches<'a>(&self, name: &'a str, extras: &GEntityNodeExtras) -> Option<&'a str> {
+        self.matchers.iter().find_map(|matcher| matcher.matches(name, extras))
+    }
 }
 
 #[derive(Default, Resource)]
@@ -27,12 +285,30 @@ pub fn new() -> Self {
         }
     }
 
-    pub fn add(&mut self, prefix: String, keep_mesh_render: bool, hook: Box<dyn Fn(EntityRef, EntityRef, &mut Commands) + Send + Sync + 'static>) {
-        self.map.push(GEntityMapEntry {
-            prefix,
-            keep_mesh_render,
-            hook,
-        });
+    pub fn add(&mut self, prefix: String, keep_mesh_render: bool, hook: impl GEntityHook) {
+        self.add_entry(GEntityMapEntry::new(hook)
+            .matching(GEntityMatcher::Prefix(prefix))
+            .keep_mesh_render(keep_mesh_render));
+    }
+
+    pub fn add_entry(&mut self, entry: GEntityMapEntry) {
+        self.map.push(entry);
+        self.map.sort_by_key(|entry| std::cmp::Reverse(entry.priority));
+    }
+
+    /// The entries running on a node in order, each with the name its hook gets.
+    fn matching_entries<'n>(&self, name: &'n str, extras: &GEntityNodeExtras) -> Vec<(&GEntityMapEntry, &'n str)> {
+        let mut entries = vec![];
+        for entry in self.map.iter() {
+            let Some(hook_name) = entry.matches(name, extras) else {
+                continue;
+            };
+            entries.push((entry, hook_name));
+            if entry.stop_after_match {
+                break;
+            }
+        }
+        entries
     }
 }
 
@@ -46,7 +322,9 @@ pub fn processs_gentity_gltf_scene(
 ) {
     for (parent_entity, instance) in unloaded_instances.iter() {
         if scene_manager.instance_is_ready(**instance) {
-            cmds.entity(parent_entity).remove::<ProcessGEntity>();
+            cmds.entity(parent_entity)
+                .remove::<ProcessGEntity>()
+                .insert(GEntityState::ProcessingHooks);
         }
         let parent_entity_ref_opt = world.get_entity(parent_entity);
         if parent_entity_ref_opt.is_none() {
@@ -54,28 +332,175 @@ pub fn processs_gentity_gltf_scene(
             continue;
         }
         let parent_entity_ref = parent_entity_ref_opt.unwrap();
-        cmds.entity(parent_entity).try_insert(RigidBody::Kinematic);
+        let mut failures = vec![];
         let entities = scene_manager
             .iter_instance_entities(**instance)
             .chain(std::iter::once(parent_entity));
         for entity_ref in entities.filter_map(|e| world.get_entity(e)) {
-            let name_opt = entity_ref.get::<Name>();
-            if let Some(name) = name_opt {
-                for entry in gentity_map.map.iter() {
-                    if name.len() > entry.prefix.len() && name.starts_with(&entry.prefix) {
-                        (entry.hook)(parent_entity_ref, entity_ref, &mut cmds);
-                        if !entry.keep_mesh_render {
-                            cmds.entity(entity_ref.id())
-                                .remove::<Visibility>()
-                                .remove::<InheritedVisibility>()
-                                .remove::<ViewVisibility>()
-                                .remove::<Aabb>()
-                                .remove::<Handle<StandardMaterial>>()
-                                .remove::<Handle<Mesh>>();
-                        }
-                    }
+            let Some(name) = entity_ref.get::<Name>() else {
+                continue;
+            };
+            let extras = match GEntityNodeExtras::parse(entity_ref.get::<GltfExtras>()) {
+                Ok(extras) => extras,
+                Err(error) => {
+                    failures.push(GEntityHookFailure {
+                        hook: "GltfExtras",
+                        node: entity_ref.id(),
+                        node_name: name.to_string(),
+                        error,
+                    });
+                    GEntityNodeExtras::default()
+                }
+            };
+            for (entry, hook_name) in gentity_map.matching_entries(name.as_str(), &extras) {
+                let context = GEntityHookContext {
+                    world,
+                    gentity: parent_entity_ref,
+                    node: entity_ref,
+                    name: hook_name,
+                    local_transform: entity_ref.get::<Transform>().copied().unwrap_or_default(),
+                    global_transform: entity_ref.get::<GlobalTransform>().copied().unwrap_or_default(),
+                    extras: extras.clone(),
+                    toml: parent_entity_ref.get::<Handle<TomlAsset>>(),
+                };
+                if let Err(error) = entry.hook.run(&context, &mut cmds) {
+                    failures.push(GEntityHookFailure {
+                        hook: entry.hook.name(),
+                        node: entity_ref.id(),
+                        node_name: name.to_string(),
+                        error,
+                    });
+                }
+                if !entry.keep_mesh_render {
+                    strip_mesh_render(world, entity_ref, &mut cmds);
                 }
             }
         }
+        if !failures.is_empty() {
+            for failure in failures.iter() {
+                warn!("GEntity hook {} failed on node '{}' ({:?}) of {:?}: {}", failure.hook, failure.node_name, failure.node, parent_entity, failure.error);
+            }
+            cmds.entity(parent_entity).try_insert(GEntityHookFailures(failures));
+        }
+    }
+}
+
+/// Removes the rendering components of a node and of the mesh primitives spawned as its children.
+fn strip_mesh_render(world: &World, node: EntityRef, cmds: &mut Commands) {
+    let primitives = node.get::<Children>()
+        .map(|children| children.iter().copied().filter(|child| world.get::<Handle<Mesh>>(*child).is_some()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    for entity in std::iter::once(node.id()).chain(primitives) {
+        cmds.entity(entity)
+            .remove::<Visibility>()
+            .remove::<InheritedVisibility>()
+            .remove::<ViewVisibility>()
+            .remove::<Aabb>()
+            .remove::<Handle<StandardMaterial>>()
+            .remove::<Handle<Mesh>>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NamedHook(&'static str);
+
+    impl GEntityHook for NamedHook {
+        fn run(&self, _context: &GEntityHookContext, _cmds: &mut Commands) -> Result<(), GEntityHookError> {
+            Ok(())
+        }
+
+        fn name(&self) -> &'static str {
+            self.0
+        }
+    }
+
+    fn extras(json: &str) -> GEntityNodeExtras {
+        GEntityNodeExtras::parse(Some(&GltfExtras { value: json.to_string() })).unwrap()
+    }
+
+    fn hooks_running_on<'n>(map: &GEntityMap, name: &'n str) -> Vec<(&'static str, &'n str)> {
+        map.matching_entries(name, &GEntityNodeExtras::default()).into_iter()
+            .map(|(entry, hook_name)| (entry.hook.name(), hook_name))
+            .collect()
+    }
+
+    #[test]
+    fn numbers_are_validated() {
+        let extras = extras(r#"{"mass": 2.5, "count": -1, "name": "door"}"#);
+        assert_eq!(extras.number("mass", 1.0, |mass| mass > 0.0).unwrap(), 2.5);
+        assert_eq!(extras.number("missing", 1.0, |mass| mass > 0.0).unwrap(), 1.0);
+        assert_eq!(extras.get_number("missing", |_| true).unwrap(), None);
+        assert_eq!(extras.get_number("count", |_| true).unwrap(), Some(-1.0));
+        for key in ["count", "name"] {
+            assert!(matches!(
+                extras.number(key, 1.0, |count| count >= 0.0),
+                Err(GEntityHookError::InvalidExtrasValue { key: invalid, .. }) if invalid == key,
+            ));
+        }
+    }
+
+    #[test]
+    fn strip_blender_suffix_removes_only_three_digit_suffixes() {
+        assert_eq!(strip_blender_suffix("seat.pilot.001"), "seat.pilot");
+        assert_eq!(strip_blender_suffix("door.042"), "door");
+        assert_eq!(strip_blender_suffix("door"), "door");
+        assert_eq!(strip_blender_suffix("door.01"), "door.01");
+        assert_eq!(strip_blender_suffix("door.abc"), "door.abc");
+        assert_eq!(strip_blender_suffix(".001"), ".001");
+    }
+
+    #[test]
+    fn prefix_strips_prefix_and_blender_suffix() {
+        let matcher = GEntityMatcher::prefix("seat.");
+        let none = GEntityNodeExtras::default();
+        assert_eq!(matcher.matches("seat.pilot", &none), Some("pilot"));
+        assert_eq!(matcher.matches("seat.pilot.001", &none), Some("pilot"));
+        assert_eq!(matcher.matches("seat.", &none), None);
+        assert_eq!(matcher.matches("thruster.main", &none), None);
+    }
+
+    #[test]
+    fn glob_translates_wildcards_and_escapes_the_rest() {
+        let matcher = GEntityMatcher::glob("thruster.*_?").unwrap();
+        let none = GEntityNodeExtras::default();
+        assert_eq!(matcher.matches("thruster.main_1", &none), Some("thruster.main_1"));
+        assert_eq!(matcher.matches("thruster.main_1.003", &none), Some("thruster.main_1"));
+        assert_eq!(matcher.matches("thruster._x", &none), Some("thruster._x"));
+        // The dot is literal, `?` needs exactly one character and the whole name has to match.
+        assert_eq!(matcher.matches("thrusterXmain_1", &none), None);
+        assert_eq!(matcher.matches("thruster.main_", &none), None);
+        assert_eq!(matcher.matches("my.thruster.main_1", &none), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn extras_matches_on_value_and_strips_suffix() {
+        let matcher = GEntityMatcher::extras("gentity", "seat");
+        assert_eq!(matcher.matches("Chair.002", &extras(r#"{"gentity": "seat"}"#)), Some("Chair"));
+        assert_eq!(matcher.matches("Chair", &extras(r#"{"gentity": "door"}"#)), None);
+        assert_eq!(matcher.matches("Chair", &GEntityNodeExtras::default()), None);
+    }
+
+    #[test]
+    fn entries_run_by_priority_then_registration_order() {
+        let mut map = GEntityMap::new();
+        map.add_entry(GEntityMapEntry::new(NamedHook("first")).matching(GEntityMatcher::prefix("node.")));
+        map.add_entry(GEntityMapEntry::new(NamedHook("high")).matching(GEntityMatcher::prefix("node.")).with_priority(10));
+        map.add_entry(GEntityMapEntry::new(NamedHook("second")).matching(GEntityMatcher::prefix("node.")));
+        map.add_entry(GEntityMapEntry::new(NamedHook("low")).matching(GEntityMatcher::prefix("node.")).with_priority(-10));
+        map.add_entry(GEntityMapEntry::new(NamedHook("other")).matching(GEntityMatcher::prefix("other.")));
+        assert_eq!(hooks_running_on(&map, "node.a"), vec![("high", "a"), ("first", "a"), ("second", "a"), ("low", "a")]);
+    }
+
+    #[test]
+    fn stop_after_match_skips_later_entries() {
+        let mut map = GEntityMap::new();
+        map.add_entry(GEntityMapEntry::new(NamedHook("generic")).matching(GEntityMatcher::glob("*").unwrap()));
+        map.add_entry(GEntityMapEntry::new(NamedHook("special")).matching(GEntityMatcher::prefix("special.")).with_priority(1).stop_after_match());
+        assert_eq!(hooks_running_on(&map, "special.a"), vec![("special", "a")]);
+        // A stopping entry that does not match lets the others run.
+        assert_eq!(hooks_running_on(&map, "plain"), vec![("generic", "plain")]);
+    }
+}
@@ -0,0 +1,126 @@
+use bevy::prelude::*;
+use bevy_xpbd_3d::math::Scalar;
+use crate::gentity::gltf::hook::{GEntityHook, GEntityHookContext, GEntityHookError, GEntityMap, GEntityMapEntry, GEntityMatcher};
+use crate::gentity::template::GEntityTemplateRegistry;
+
+/// Sea level pressure on earth, in kPa.
+pub const DEFAULT_PRESSURE: Scalar = 101.325;
+pub const DEFAULT_O2_FRACTION: Scalar = 0.21;
+/// 20 °C, in kelvin.
+pub const DEFAULT_TEMPERATURE: Scalar = 293.15;
+/// How fast gas flows through an open door, as the fraction of the pressure difference equalized per second.
+pub const DEFAULT_DOOR_FLOW: Scalar = 1.0;
+
+/// The name doors use to connect a volume to the outside.
+pub const VACUUM: &str = "vacuum";
+
+/// A sealed box of gas. The box is the node with its scale as size along each axis.
+#[derive(Component)]
+pub struct GEntityAtmosphere {
+    pub gentity: Entity,
+    pub name: String,
+    /// In cubic meters.
+    pub volume: Scalar,
+    /// In kPa.
+    pub pressure: Scalar,
+    /// The fraction of oxygen, 0 to 1.
+    pub o2_fraction: Scalar,
+    /// In kelvin.
+    pub temperature: Scalar,
+}
+
+impl GEntityAtmosphere {
+    pub fn contains(transform: &GlobalTransform, point: Vec3) -> bool {
+        transform.affine().inverse().transform_point3(point).abs().max_element() <= 0.5
+    }
+}
+
+/// A door or valve connecting two [GEntityAtmosphere]s of the same GEntity, or one with the outside.
+#[derive(Component)]
+pub struct GEntityDoor {
+    pub gentity: Entity,
+    pub name: String,
+    /// The names of the connected volumes, [VACUUM] for the outside.
+    pub connects: [String; 2],
+    pub open: bool,
+    /// The fraction of the pressure difference equalized per second while open.
+    pub flow: Scalar,
+}
+
+pub struct AtmosphereHook;
+
+impl GEntityHook for AtmosphereHook {
+    fn run(&self, context: &GEntityHookContext, cmds: &mut Commands) -> Result<(), GEntityHookError> {
+        let scale = context.node_transform()?.scale.as_dvec3();
+        cmds.entity(context.node.id()).insert(GEntityAtmosphere {
+            gentity: context.gentity.id(),
+            name: context.name.to_string(),
+            volume: scale.x * scale.y * scale.z,
+            pressure: context.extras.number("pressure", DEFAULT_PRESSURE, |pressure| pressure >= 0.0)?,
+            o2_fraction: context.extras.number("o2", DEFAULT_O2_FRACTION, |o2| (0.0..=1.0).contains(&o2))?,
+            temperature: context.extras.number("temperature", DEFAULT_TEMPERATURE, |temperature| temperature > 0.0)?,
+        });
+        Ok(())
+    }
+}
+
+pub struct DoorHook;
+
+impl GEntityHook for DoorHook {
+    fn run(&self, context: &GEntityHookContext, cmds: &mut Commands) -> Result<(), GEntityHookError> {
+        let connects = context.extras.get_str("connects")
+            .map(|connects| connects.split(',').map(|name| name.trim().to_string()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let [first, second] = <[String; 2]>::try_from(connects).map_err(|_| GEntityHookError::InvalidExtrasValue {
+            key: "connects".into(),
+            reason: format!("expected two volume names separated by a comma (eg. \"cabin,{}\")", VACUUM),
+        })?;
+        cmds.entity(context.node.id()).insert(GEntityDoor {
+            gentity: context.gentity.id(),
+            name: context.name.to_string(),
+            connects: [first, second],
+            open: context.extras.get_bool("open").unwrap_or(false),
+            flow: context.extras.number("flow", DEFAULT_DOOR_FLOW, |flow| flow >= 0.0)?,
+        });
+        Ok(())
+    }
+}
+
+pub fn setup_pp_atmosphere(
+    mut gentity_map: ResMut<GEntityMap>,
+    mut template_registry: ResMut<GEntityTemplateRegistry>,
+) {
+    gentity_map.add_entry(GEntityMapEntry::new(AtmosphereHook)
+        .matching(GEntityMatcher::prefix("atmo."))
+        .matching(GEntityMatcher::extras("gentity", "atmo")));
+    gentity_map.add_entry(GEntityMapEntry::new(DoorHook)
+        .matching(GEntityMatcher::prefix("door."))
+        .matching(GEntityMatcher::extras("gentity", "door")));
+    template_registry.register_with::<GEntityAtmosphere>(|atmosphere, entity_map| GEntityAtmosphere {
+        gentity: entity_map.get(&atmosphere.gentity).copied().unwrap_or(atmosphere.gentity),
+        name: atmosphere.name.clone(),
+        volume: atmosphere.volume,
+        pressure: atmosphere.pressure,
+        o2_fraction: atmosphere.o2_fraction,
+        temperature: atmosphere.temperature,
+    });
+    template_registry.register_with::<GEntityDoor>(|door, entity_map| GEntityDoor {
+        gentity: entity_map.get(&door.gentity).copied().unwrap_or(door.gentity),
+        name: door.name.clone(),
+        connects: door.connects.clone(),
+        open: door.open,
+        flow: door.flow,
+    });
+}
+
+pub enum AtmosphereRequest {
+    SetPressure { volume: String, pressure: Scalar },
+    SetDoor { door: String, open: bool },
+}
+
+/// Changes an atmosphere volume or door of `gentity`, addressed by name.
+#[derive(Event)]
+pub struct GEntityAtmosphereRequest {
+    pub gentity: Entity,
+    pub request: AtmosphereRequest,
+}
@@ -1,30 +1,83 @@
 use bevy::prelude::*;
-use bevy_xpbd_3d::parry::shape::SharedShape;
 use bevy_xpbd_3d::prelude::*;
-use crate::gentity::gltf::hook::GEntityMap;
+use crate::gentity::gltf::shape::build_collider;
+use crate::gentity::asset_loaders::toml_asset_loader::TomlAsset;
+use crate::gentity::gltf::hook::{GEntityHook, GEntityHookContext, GEntityHookError, GEntityMap, GEntityMapEntry, GEntityMatcher, ProcessGEntity};
 
+/// Turns collider nodes into child colliders of the [RigidBody] on the GEntity root.
+///
+/// The nodes stay in the scene hierarchy, so bevy_xpbd attaches them to the root body as one compound
+/// body and computes its mass properties from them. Despawning the GEntity despawns them with it.
+pub struct CollisionHook;
+
+/// Marks GEntities whose `[physics]` mass or center of mass still has to be applied to their colliders.
 #[derive(Default, Component)]
-pub struct JointEntities(Vec<Entity>);
+pub struct GEntityMassPending;
+
+impl GEntityHook for CollisionHook {
+    fn run(&self, context: &GEntityHookContext, cmds: &mut Commands) -> Result<(), GEntityHookError> {
+        let transform = context.node_transform()?;
+        let collider = build_collider(context)?;
+        let mut cloned_transform = transform.clone();
+        cloned_transform.scale = Vec3::ONE;
+        let mut node_cmds = cmds.entity(context.node.id());
+        node_cmds
+            .remove::<Transform>()
+            .insert(cloned_transform)
+            .insert(collider)
+        ;
+        if let Some(toml_asset) = context.toml_asset() {
+            if let Some(density) = toml_asset.physics.density {
+                node_cmds.insert(ColliderDensity(density));
+            }
+            if let Some(collision_layers) = toml_asset.physics.collision_layers() {
+                node_cmds.insert(collision_layers);
+            }
+        }
+        Ok(())
+    }
+}
+
 pub fn setup_pp_collision(
     mut gentity_map: ResMut<GEntityMap>
 ) {
-    gentity_map.add("collider.".into(), false, Box::new(|parent, entity, cmds| {
-        let transform_opt = entity.get::<Transform>();
-        if let Some(transform) = transform_opt {
-            let mut cloned_transform = transform.clone();
-            cloned_transform.scale = Vec3::ONE;
-            let joint_child = cmds.spawn(FixedJoint::new(entity.id(), parent.id())).id();
-            cmds
-                .entity(entity.id())
-                .remove::<Transform>()
-                .insert(cloned_transform)
-                .insert(RigidBody::Dynamic) // ToDo: Figure out why Position is not updated
-                .insert(SharedShape)
-                .insert(Collider::cuboid(transform.scale.x as f64, transform.scale.y as f64, transform.scale.z as f64))
-                .insert(JointEntities(vec![joint_child]))
-            ;
-        } else {
-            warn!("No transform found for entity: {:?}", entity.id());
+    gentity_map.add_entry(GEntityMapEntry::new(CollisionHook)
+        .matching(GEntityMatcher::prefix("collider."))
+        .matching(GEntityMatcher::extras("gentity", "collider")));
+}
+
+/// Scales the density of all colliders of a GEntity so their combined mass is the configured
+/// `[physics] mass`, and applies the configured center of mass.
+pub fn configure_gentity_mass(
+    pending: Query<(Entity, &Handle<TomlAsset>), (With<GEntityMassPending>, Without<ProcessGEntity>)>,
+    children: Query<&Children>,
+    colliders: Query<&Collider, Without<Sensor>>,
+    assets: Res<Assets<TomlAsset>>,
+    mut cmds: Commands,
+) {
+    for (entity, toml_asset_handle) in pending.iter() {
+        let Some(asset) = assets.get(toml_asset_handle) else {
+            continue;
+        };
+        cmds.entity(entity).remove::<GEntityMassPending>();
+        if let Some(mass) = asset.physics.mass {
+            let collider_entities = children.iter_descendants(entity)
+                .filter(|descendant| colliders.contains(*descendant))
+                .collect::<Vec<_>>();
+            let unit_mass: f64 = collider_entities.iter()
+                .filter_map(|descendant| colliders.get(*descendant).ok())
+                .map(|collider| collider.mass_properties(1.0).mass.0)
+                .sum();
+            if unit_mass > 0.0 {
+                for collider_entity in collider_entities {
+                    cmds.entity(collider_entity).insert(ColliderDensity(mass / unit_mass));
+                }
+            } else {
+                cmds.entity(entity).insert(Mass(mass));
+            }
+        }
+        if let Some(center_of_mass) = asset.physics.center_of_mass {
+            cmds.entity(entity).insert(CenterOfMass(center_of_mass));
         }
-    }));
-}
\ No newline at end of file
+    }
+}
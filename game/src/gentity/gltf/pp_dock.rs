@@ -0,0 +1,286 @@
+use bevy::prelude::*;
+use bevy_xpbd_3d::math::{Scalar, Vector};
+use bevy_xpbd_3d::prelude::*;
+use crate::gentity::gltf::hook::{GEntityHook, GEntityHookContext, GEntityHookError, GEntityMap, GEntityMapEntry, GEntityMatcher};
+use crate::gentity::script::runtime::{GEntityNotification, GEntityScriptCall};
+use crate::gentity::template::GEntityTemplateRegistry;
+
+const DEFAULT_ALIGNMENT_TOLERANCE_DEGREES: Scalar = 5.0;
+const DEFAULT_CAPTURE_RANGE: Scalar = 1.0;
+const DEFAULT_MAX_RELATIVE_SPEED: Scalar = 0.5;
+const DEFAULT_SEPARATION_IMPULSE: Scalar = 1000.0;
+
+/// A docking port facing outwards along the forward axis (-Z) of its node.
+///
+/// Two ports dock when their size classes match, they face each other within both alignment
+/// tolerances, are within both capture ranges and move slower relative to each other than both
+/// maximum speeds. The docking joint keeps both GEntities in the same orientation, so they also
+/// have to be oriented alike within both alignment tolerances, else docking is refused.
+#[derive(Component)]
+pub struct GEntityDockingPort {
+    pub gentity: Entity,
+    pub name: String,
+    pub size: u32,
+    /// The maximum angle between the facing of both ports, in radians.
+    pub alignment_tolerance: Scalar,
+    /// The maximum distance between both ports, in meters.
+    pub capture_range: Scalar,
+    /// The maximum relative speed of both GEntities, in meters per second.
+    pub max_relative_speed: Scalar,
+    /// The impulse pushing the GEntities apart when undocking, in newton seconds.
+    pub separation_impulse: Scalar,
+    /// The [GEntityDocking] joint while docked.
+    pub docked_with: Option<Entity>,
+    /// The port this one undocked from or refused to dock with, ignored until they left each others
+    /// capture range so they do not redock or repeat the refusal right away.
+    ignored_port: Option<Entity>,
+}
+
+impl GEntityDockingPort {
+    /// A port of size class 0 with the default tolerances.
+    pub fn new(gentity: Entity, name: impl Into<String>) -> Self {
+        Self {
+            gentity,
+            name: name.into(),
+            size: 0,
+            alignment_tolerance: DEFAULT_ALIGNMENT_TOLERANCE_DEGREES.to_radians(),
+            capture_range: DEFAULT_CAPTURE_RANGE,
+            max_relative_speed: DEFAULT_MAX_RELATIVE_SPEED,
+            separation_impulse: DEFAULT_SEPARATION_IMPULSE,
+            docked_with: None,
+            ignored_port: None,
+        }
+    }
+}
+
+/// The joint entity locking two docked GEntities together.
+#[derive(Component)]
+pub struct GEntityDocking {
+    pub port_1: Entity,
+    pub port_2: Entity,
+}
+
+#[derive(Event)]
+pub struct GEntityDocked {
+    pub port_1: Entity,
+    pub port_2: Entity,
+}
+
+/// Asks `gentity` to undock from everything, or only at the port named `port`.
+#[derive(Event)]
+pub struct GEntityUndock {
+    pub gentity: Entity,
+    pub port: Option<String>,
+}
+
+#[derive(Event)]
+pub struct GEntityUndocked {
+    pub port_1: Entity,
+    pub port_2: Entity,
+}
+
+pub struct DockingPortHook;
+
+impl GEntityHook for DockingPortHook {
+    fn run(&self, context: &GEntityHookContext, cmds: &mut Commands) -> Result<(), GEntityHookError> {
+        let extras = &context.extras;
+        // Size classes are whole numbers, so a 1.7 port does not dock with a 1 port.
+        let size = extras.number("size", 0.0, |size| size >= 0.0 && size.fract() == 0.0 && size <= u32::MAX as f64)?;
+        cmds.entity(context.node.id()).insert(GEntityDockingPort {
+            size: size as u32,
+            alignment_tolerance: extras.number("tolerance", DEFAULT_ALIGNMENT_TOLERANCE_DEGREES, |tolerance| tolerance >= 0.0)?.to_radians(),
+            capture_range: extras.number("capture_range", DEFAULT_CAPTURE_RANGE, |range| range >= 0.0)?,
+            max_relative_speed: extras.number("max_speed", DEFAULT_MAX_RELATIVE_SPEED, |speed| speed >= 0.0)?,
+            separation_impulse: extras.number("separation_impulse", DEFAULT_SEPARATION_IMPULSE, |impulse| impulse >= 0.0)?,
+            ..GEntityDockingPort::new(context.gentity.id(), context.name)
+        });
+        Ok(())
+    }
+}
+
+pub fn setup_pp_dock(
+    mut gentity_map: ResMut<GEntityMap>,
+    mut template_registry: ResMut<GEntityTemplateRegistry>,
+) {
+    gentity_map.add_entry(GEntityMapEntry::new(DockingPortHook)
+        .matching(GEntityMatcher::prefix("dock."))
+        .matching(GEntityMatcher::extras("gentity", "dock")));
+    template_registry.register_with::<GEntityDockingPort>(|port, entity_map| GEntityDockingPort {
+        gentity: entity_map.get(&port.gentity).copied().unwrap_or(port.gentity),
+        name: port.name.clone(),
+        size: port.size,
+        alignment_tolerance: port.alignment_tolerance,
+        capture_range: port.capture_range,
+        max_relative_speed: port.max_relative_speed,
+        separation_impulse: port.separation_impulse,
+        docked_with: None,
+        ignored_port: None,
+    });
+}
+
+fn relative_speed(velocities: &Query<&LinearVelocity>, gentity_1: Entity, gentity_2: Entity) -> Scalar {
+    let velocity = |gentity| velocities.get(gentity).map_or(Vector::ZERO, |velocity| velocity.0);
+    (velocity(gentity_1) - velocity(gentity_2)).length()
+}
+
+pub fn dock_aligned_ports(
+    mut ports: Query<(Entity, &mut GEntityDockingPort, &GlobalTransform)>,
+    gentities: Query<&GlobalTransform>,
+    velocities: Query<&LinearVelocity>,
+    mut docked_events: EventWriter<GEntityDocked>,
+    mut script_calls: EventWriter<GEntityScriptCall>,
+    mut notifications: EventWriter<GEntityNotification>,
+    mut cmds: Commands,
+) {
+    let free_ports = ports.iter()
+        .filter(|(_, port, _)| port.docked_with.is_none())
+        .map(|(entity, port, transform)| (entity, port.gentity, transform.translation(), transform.forward()))
+        .collect::<Vec<_>>();
+    for (index, (entity_1, gentity_1, translation_1, forward_1)) in free_ports.iter().enumerate() {
+        for (entity_2, gentity_2, translation_2, forward_2) in free_ports.iter().skip(index + 1) {
+            if gentity_1 == gentity_2 {
+                continue;
+            }
+            let Ok([(_, mut port_1, transform_1), (_, mut port_2, transform_2)]) = ports.get_many_mut([*entity_1, *entity_2]) else {
+                continue;
+            };
+            if port_1.docked_with.is_some() || port_2.docked_with.is_some() {
+                continue;
+            }
+            let distance = translation_1.distance(*translation_2) as Scalar;
+            if port_1.ignored_port == Some(*entity_2) || port_2.ignored_port == Some(*entity_1) {
+                if distance > port_1.capture_range.max(port_2.capture_range) {
+                    port_1.ignored_port = None;
+                    port_2.ignored_port = None;
+                }
+                continue;
+            }
+            if port_1.size != port_2.size || distance > port_1.capture_range.min(port_2.capture_range) {
+                continue;
+            }
+            let misalignment = forward_1.angle_between(-*forward_2) as Scalar;
+            if misalignment > port_1.alignment_tolerance.min(port_2.alignment_tolerance) {
+                continue;
+            }
+            if relative_speed(&velocities, *gentity_1, *gentity_2) > port_1.max_relative_speed.min(port_2.max_relative_speed) {
+                continue;
+            }
+            let (Ok(gentity_transform_1), Ok(gentity_transform_2)) = (gentities.get(*gentity_1), gentities.get(*gentity_2)) else {
+                continue;
+            };
+            // The fixed joint also aligns the orientation of both bodies, it cannot hold them at the
+            // angle their ports are placed at, so only GEntities oriented alike dock.
+            let misorientation = gentity_transform_1.compute_transform().rotation
+                .angle_between(gentity_transform_2.compute_transform().rotation) as Scalar;
+            if misorientation > port_1.alignment_tolerance.min(port_2.alignment_tolerance) {
+                port_1.ignored_port = Some(*entity_2);
+                port_2.ignored_port = Some(*entity_1);
+                notifications.send(GEntityNotification {
+                    entity: *gentity_1,
+                    text: format!("Cannot dock {} with {}, both have to be oriented alike ({:.0}° apart)", port_1.name, port_2.name, misorientation.to_degrees()),
+                });
+                continue;
+            }
+            let joint = cmds.spawn((
+                FixedJoint::new(*gentity_1, *gentity_2)
+                    .with_local_anchor_1(transform_1.reparented_to(gentity_transform_1).translation.as_dvec3())
+                    .with_local_anchor_2(transform_2.reparented_to(gentity_transform_2).translation.as_dvec3()),
+                GEntityDocking {
+                    port_1: *entity_1,
+                    port_2: *entity_2,
+                },
+            )).id();
+            port_1.docked_with = Some(joint);
+            port_2.docked_with = Some(joint);
+            docked_events.send(GEntityDocked {
+                port_1: *entity_1,
+                port_2: *entity_2,
+            });
+            script_calls.send(GEntityScriptCall::new(*gentity_1, "onDocked", vec![port_1.name.clone().into(), port_2.name.clone().into()]));
+            script_calls.send(GEntityScriptCall::new(*gentity_2, "onDocked", vec![port_2.name.clone().into(), port_1.name.clone().into()]));
+        }
+    }
+}
+
+pub fn undock_requested_ports(
+    mut requests: EventReader<GEntityUndock>,
+    mut ports: Query<(&mut GEntityDockingPort, &GlobalTransform)>,
+    dockings: Query<&GEntityDocking>,
+    mut bodies: Query<(&mut LinearVelocity, &InverseMass)>,
+    mut undocked_events: EventWriter<GEntityUndocked>,
+    mut script_calls: EventWriter<GEntityScriptCall>,
+    mut cmds: Commands,
+) {
+    for request in requests.read() {
+        let joints = ports.iter()
+            .filter(|(port, _)| port.gentity == request.gentity && request.port.as_ref().map_or(true, |name| *name == port.name))
+            .filter_map(|(port, _)| port.docked_with)
+            .collect::<Vec<_>>();
+        for joint in joints {
+            let Ok(docking) = dockings.get(joint) else {
+                continue;
+            };
+            let Ok([(mut port_1, transform_1), (mut port_2, transform_2)]) = ports.get_many_mut([docking.port_1, docking.port_2]) else {
+                continue;
+            };
+            cmds.entity(joint).despawn();
+            port_1.ignored_port = Some(docking.port_2);
+            port_2.ignored_port = Some(docking.port_1);
+            for (port, transform) in [(&mut port_1, transform_1), (&mut port_2, transform_2)] {
+                port.docked_with = None;
+                if let Ok((mut linear_velocity, inverse_mass)) = bodies.get_mut(port.gentity) {
+                    // Push each GEntity away from the port it docked with.
+                    linear_velocity.0 -= transform.forward().as_dvec3() * port.separation_impulse * inverse_mass.0;
+                }
+            }
+            undocked_events.send(GEntityUndocked {
+                port_1: docking.port_1,
+                port_2: docking.port_2,
+            });
+            script_calls.send(GEntityScriptCall::new(port_1.gentity, "onUndocked", vec![port_1.name.clone().into(), port_2.name.clone().into()]));
+            script_calls.send(GEntityScriptCall::new(port_2.gentity, "onUndocked", vec![port_2.name.clone().into(), port_1.name.clone().into()]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+    use bevy::gltf::GltfExtras;
+    use crate::gentity::gltf::hook::GEntityNodeExtras;
+    use super::*;
+
+    /// Runs the [DockingPortHook] on a node with the custom properties `json`, returning its size class.
+    fn port_size(json: &str) -> Result<u32, GEntityHookError> {
+        let mut world = World::new();
+        let gentity = world.spawn(TransformBundle::default()).id();
+        let node = world.spawn(TransformBundle::default()).set_parent(gentity).id();
+        let mut queue = CommandQueue::default();
+        let context = GEntityHookContext {
+            world: &world,
+            gentity: world.entity(gentity),
+            node: world.entity(node),
+            name: "front",
+            local_transform: Transform::IDENTITY,
+            global_transform: GlobalTransform::IDENTITY,
+            extras: GEntityNodeExtras::parse(Some(&GltfExtras { value: json.into() }))?,
+            toml: None,
+        };
+        DockingPortHook.run(&context, &mut Commands::new(&mut queue, &world))?;
+        queue.apply(&mut world);
+        Ok(world.get::<GEntityDockingPort>(node).unwrap().size)
+    }
+
+    #[test]
+    fn size_classes_are_whole_numbers() {
+        assert_eq!(port_size("{}").unwrap(), 0);
+        assert_eq!(port_size(r#"{"size": 2}"#).unwrap(), 2);
+        assert_eq!(port_size(r#"{"size": 3.0}"#).unwrap(), 3);
+        for json in [r#"{"size": 1.7}"#, r#"{"size": -1}"#, r#"{"size": "large"}"#] {
+            assert!(matches!(
+                port_size(json),
+                Err(GEntityHookError::InvalidExtrasValue { key, .. }) if key == "size",
+            ), "{}", json);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy_xpbd_3d::math::{Scalar, Vector};
+use crate::gentity::gltf::hook::{GEntityHook, GEntityHookContext, GEntityHookError, GEntityMap, GEntityMapEntry, GEntityMatcher};
+use crate::gentity::template::GEntityTemplateRegistry;
+
+/// Standard gravity, used for volumes not setting a `strength`.
+pub const DEFAULT_GRAVITY_STRENGTH: Scalar = 9.80665;
+
+/// An oriented box of artificial gravity. The box is the node with its scale as size along each axis.
+#[derive(Component)]
+pub struct GEntityGravityVolume {
+    pub gentity: Entity,
+    pub name: String,
+    /// In meters per second squared.
+    pub strength: Scalar,
+    /// The direction gravity pulls in, in the node frame.
+    pub direction: Vector,
+}
+
+impl GEntityGravityVolume {
+    /// Returns the acceleration inside the volume if `point` is inside of it.
+    pub fn acceleration_at(&self, transform: &GlobalTransform, point: Vec3) -> Option<Vector> {
+        let local = transform.affine().inverse().transform_point3(point);
+        if local.abs().max_element() > 0.5 {
+            return None;
+        }
+        Some(transform.affine().transform_vector3(self.direction.as_vec3()).as_dvec3().normalize_or_zero() * self.strength)
+    }
+}
+
+fn parse_axis(axis: &str) -> Option<Vector> {
+    match axis {
+        "x" | "+x" => Some(DVec3::X),
+        "-x" => Some(DVec3::NEG_X),
+        "y" | "+y" => Some(DVec3::Y),
+        "-y" => Some(DVec3::NEG_Y),
+        "z" | "+z" => Some(DVec3::Z),
+        "-z" => Some(DVec3::NEG_Z),
+        _ => None,
+    }
+}
+
+pub struct GravityVolumeHook;
+
+impl GEntityHook for GravityVolumeHook {
+    fn run(&self, context: &GEntityHookContext, cmds: &mut Commands) -> Result<(), GEntityHookError> {
+        let strength = context.extras.number("strength", DEFAULT_GRAVITY_STRENGTH, |_| true)?;
+        let direction = match context.extras.get_str("direction") {
+            None => DVec3::NEG_Y,
+            Some(axis) => parse_axis(axis).ok_or_else(|| GEntityHookError::InvalidExtrasValue {
+                key: "direction".into(),
+                reason: format!("unknown axis '{}', expected one of x, -x, y, -y, z or -z", axis),
+            })?,
+        };
+        cmds.entity(context.node.id()).insert(GEntityGravityVolume {
+            gentity: context.gentity.id(),
+            name: context.name.to_string(),
+            strength,
+            direction,
+        });
+        Ok(())
+    }
+}
+
+pub fn setup_pp_gravity(
+    mut gentity_map: ResMut<GEntityMap>,
+    mut template_registry: ResMut<GEntityTemplateRegistry>,
+) {
+    gentity_map.add_entry(GEntityMapEntry::new(GravityVolumeHook)
+        .matching(GEntityMatcher::prefix("gravity."))
+        .matching(GEntityMatcher::extras("gentity", "gravity")));
+    template_registry.register_with::<GEntityGravityVolume>(|volume, entity_map| GEntityGravityVolume {
+        gentity: entity_map.get(&volume.gentity).copied().unwrap_or(volume.gentity),
+        name: volume.name.clone(),
+        strength: volume.strength,
+        direction: volume.direction,
+    });
+}
@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+use bevy_xpbd_3d::math::Scalar;
+use bevy_xpbd_3d::prelude::*;
+use crate::gentity::gltf::hook::{GEntityHook, GEntityHookContext, GEntityHookError, GEntityMap, GEntityMapEntry, GEntityMatcher};
+use crate::gentity::gltf::pp_seat::GEntitySeated;
+use crate::gentity::gltf::shape::build_collider;
+use crate::gentity::script::runtime::GEntityScriptCall;
+use crate::gentity::template::GEntityTemplateRegistry;
+use crate::player::Player;
+
+pub const INTERACTION_KEY: KeyCode = KeyCode::F;
+
+/// How far away from the camera nodes can be interacted with, in meters.
+pub const INTERACTION_DISTANCE: Scalar = 2.5;
+
+/// A button or panel the player can activate by looking at it and pressing [INTERACTION_KEY].
+#[derive(Component)]
+pub struct GEntityInteract {
+    pub gentity: Entity,
+    pub name: String,
+}
+
+/// The player looked at `node` and pressed [INTERACTION_KEY].
+#[derive(Event)]
+pub struct GEntityNodeActivated {
+    pub node: Entity,
+    pub player: Entity,
+}
+
+#[derive(Event)]
+pub struct GEntityInteracted {
+    pub gentity: Entity,
+    pub node: Entity,
+    pub name: String,
+    pub player: Entity,
+}
+
+pub struct InteractHook;
+
+impl GEntityHook for InteractHook {
+    fn run(&self, context: &GEntityHookContext, cmds: &mut Commands) -> Result<(), GEntityHookError> {
+        let transform = context.node_transform()?;
+        let collider = build_collider(context)?;
+        let mut cloned_transform = transform.clone();
+        cloned_transform.scale = Vec3::ONE;
+        cmds.entity(context.node.id())
+            .remove::<Transform>()
+            .insert(cloned_transform)
+            .insert(collider)
+            .insert(Sensor)
+            .insert(GEntityInteract {
+                gentity: context.gentity.id(),
+                name: context.name.to_string(),
+            })
+        ;
+        Ok(())
+    }
+}
+
+pub fn setup_pp_interact(
+    mut gentity_map: ResMut<GEntityMap>,
+    mut template_registry: ResMut<GEntityTemplateRegistry>,
+) {
+    gentity_map.add_entry(GEntityMapEntry::new(InteractHook)
+        .matching(GEntityMatcher::prefix("interact."))
+        .matching(GEntityMatcher::extras("gentity", "interact")));
+    template_registry.register_with::<GEntityInteract>(|interact, entity_map| GEntityInteract {
+        gentity: entity_map.get(&interact.gentity).copied().unwrap_or(interact.gentity),
+        name: interact.name.clone(),
+    });
+}
+
+/// Casts a ray from the player camera when [INTERACTION_KEY] is pressed and activates the node it hits.
+pub fn activate_looked_at_nodes(
+    keyboard_input: Res<Input<KeyCode>>,
+    spatial_query: SpatialQuery,
+    players: Query<(Entity, &Position, &Rotation, &Children), (With<Player>, Without<GEntitySeated>)>,
+    cameras: Query<&Transform, With<Camera3d>>,
+    mut left_seats: RemovedComponents<GEntitySeated>,
+    mut activated_events: EventWriter<GEntityNodeActivated>,
+) {
+    if !keyboard_input.just_pressed(INTERACTION_KEY) {
+        return;
+    }
+    // The key press that got players out of their seat must not put them back in.
+    let left_seats = left_seats.read().collect::<Vec<_>>();
+    for (player, position, rotation, children) in players.iter() {
+        if left_seats.contains(&player) {
+            continue;
+        }
+        let Some(camera_transform) = children.iter().find_map(|child| cameras.get(*child).ok()) else {
+            continue;
+        };
+        let origin = position.0 + rotation.rotate(camera_transform.translation.as_dvec3());
+        let direction = rotation.rotate((camera_transform.rotation * Vec3::NEG_Z).as_dvec3());
+        let filter = SpatialQueryFilter::new().without_entities([player]);
+        if let Some(hit) = spatial_query.cast_ray(origin, direction, INTERACTION_DISTANCE, true, filter) {
+            activated_events.send(GEntityNodeActivated {
+                node: hit.entity,
+                player,
+            });
+        }
+    }
+}
+
+pub fn interact_with_activated_nodes(
+    mut activated_events: EventReader<GEntityNodeActivated>,
+    interacts: Query<&GEntityInteract>,
+    mut interacted_events: EventWriter<GEntityInteracted>,
+    mut script_calls: EventWriter<GEntityScriptCall>,
+) {
+    for activated in activated_events.read() {
+        let Ok(interact) = interacts.get(activated.node) else {
+            continue;
+        };
+        interacted_events.send(GEntityInteracted {
+            gentity: interact.gentity,
+            node: activated.node,
+            name: interact.name.clone(),
+            player: activated.player,
+        });
+        script_calls.send(GEntityScriptCall::new(interact.gentity, "onInteract", vec![interact.name.clone().into()]));
+    }
+}
@@ -0,0 +1,356 @@
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy_xpbd_3d::math::{Quaternion, Scalar, Vector};
+use bevy_xpbd_3d::prelude::*;
+use crate::gentity::gltf::hook::{GEntityHook, GEntityHookContext, GEntityHookError, GEntityMap, GEntityMapEntry, GEntityMatcher};
+use crate::gentity::template::{GEntityTemplateEntityMap, GEntityTemplateRegistry};
+
+const DEFAULT_STIFFNESS: Scalar = 1000.0;
+const DEFAULT_DAMPING: Scalar = 100.0;
+const DEFAULT_MAX_FORCE: Scalar = 500.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GEntityJointKind {
+    /// Rotates around the axis, positions in radians.
+    Hinge,
+    /// Slides along the axis, positions in meters.
+    Slider,
+}
+
+/// A joint between the GEntity root and one of its nodes turned into a body of its own.
+#[derive(Component)]
+pub struct GEntityJoint {
+    pub gentity: Entity,
+    pub name: String,
+    pub kind: GEntityJointKind,
+    pub body: Entity,
+    /// The joint axis in the frame of the GEntity root.
+    pub axis: Vector,
+    /// The body rotation and translation in the frame of the GEntity root at position 0.
+    pub rest_rotation: Quaternion,
+    pub rest_translation: Vector,
+}
+
+/// Drives a [GEntityJoint] to `target` like a spring, pushing with at most `max_force`
+/// (newton for sliders, newton meters for hinges) so the body can be blocked.
+#[derive(Component)]
+pub struct GEntityJointMotor {
+    pub target: Scalar,
+    pub stiffness: Scalar,
+    pub damping: Scalar,
+    pub max_force: Scalar,
+}
+
+/// Sets the motor target of the joint named `joint` of `gentity`.
+#[derive(Event)]
+pub struct GEntityJointTarget {
+    pub gentity: Entity,
+    pub joint: String,
+    pub target: Scalar,
+}
+
+pub struct JointHook(pub GEntityJointKind);
+
+impl GEntityHook for JointHook {
+    fn run(&self, context: &GEntityHookContext, cmds: &mut Commands) -> Result<(), GEntityHookError> {
+        let kind = self.0;
+        let body_name = context.extras.get_str("body").ok_or_else(|| GEntityHookError::InvalidExtrasValue {
+            key: "body".into(),
+            reason: "missing the name of the node to move".into(),
+        })?;
+        let body = context.find_node(body_name).ok_or_else(|| GEntityHookError::InvalidExtrasValue {
+            key: "body".into(),
+            reason: format!("no node named '{}'", body_name),
+        })?;
+        let local_axis = match context.extras.get_str("axis").unwrap_or("y") {
+            "x" => DVec3::X,
+            "y" => DVec3::Y,
+            "z" => DVec3::Z,
+            axis => return Err(GEntityHookError::InvalidExtrasValue {
+                key: "axis".into(),
+                reason: format!("unknown axis '{}', expected x, y or z", axis),
+            }),
+        };
+        // Hinge limits are given in degrees, slider limits in meters.
+        let to_position = |value: Scalar| match kind {
+            GEntityJointKind::Hinge => value.to_radians(),
+            GEntityJointKind::Slider => value,
+        };
+        let min = context.extras.get_number("min", |_| true)?.map(to_position);
+        let max = context.extras.get_number("max", |_| true)?.map(to_position);
+        let joint_transform = context.transform_in_gentity(context.node.id())?;
+        let body_transform = context.transform_in_gentity(body.id())?;
+        let axis = (joint_transform.rotation * local_axis.as_vec3()).as_dvec3().normalize();
+        let anchor = joint_transform.translation;
+        let anchor_1 = anchor.as_dvec3();
+        let anchor_2 = body_transform.compute_affine().inverse().transform_point3(anchor).as_dvec3();
+        let gentity = context.gentity.id();
+
+        let mut node_cmds = cmds.entity(context.node.id());
+        // bevy_xpbd uses the same axis in the frame of both bodies, so the body has to share the
+        // orientation of the root at rest.
+        match kind {
+            GEntityJointKind::Hinge => {
+                let mut joint = RevoluteJoint::new(gentity, body.id())
+                    .with_local_anchor_1(anchor_1)
+                    .with_local_anchor_2(anchor_2)
+                    .with_aligned_axis(axis);
+                if let (Some(min), Some(max)) = (min, max) {
+                    joint = joint.with_angle_limits(min, max);
+                }
+                node_cmds.insert(joint);
+            }
+            GEntityJointKind::Slider => {
+                let mut joint = PrismaticJoint::new(gentity, body.id())
+                    .with_local_anchor_1(anchor_1)
+                    .with_local_anchor_2(anchor_2)
+                    .with_free_axis(axis);
+                if let (Some(min), Some(max)) = (min, max) {
+                    joint = joint.with_limits(min, max);
+                }
+                node_cmds.insert(joint);
+            }
+        }
+        node_cmds.insert(GEntityJoint {
+            gentity,
+            name: context.name.to_string(),
+            kind,
+            body: body.id(),
+            axis,
+            rest_rotation: body_transform.rotation.as_f64(),
+            rest_translation: body_transform.translation.as_dvec3(),
+        });
+        let max_force = context.extras.number("max_force", DEFAULT_MAX_FORCE, |_| true)?;
+        if max_force > 0.0 {
+            node_cmds.insert(GEntityJointMotor {
+                target: to_position(context.extras.number("target", 0.0, |_| true)?),
+                stiffness: context.extras.number("stiffness", DEFAULT_STIFFNESS, |_| true)?,
+                damping: context.extras.number("damping", DEFAULT_DAMPING, |_| true)?,
+                max_force,
+            });
+        }
+        cmds.entity(body.id()).insert((
+            RigidBody::Dynamic,
+            ExternalForce::default().with_persistence(false),
+            ExternalTorque::default().with_persistence(false),
+        ));
+        // The root takes the reaction of the motor.
+        cmds.entity(gentity).insert((
+            ExternalForce::default().with_persistence(false),
+            ExternalTorque::default().with_persistence(false),
+        ));
+        Ok(())
+    }
+}
+
+fn map_entity(entity_map: &GEntityTemplateEntityMap, entity: Entity) -> Entity {
+    entity_map.get(&entity).copied().unwrap_or(entity)
+}
+
+pub fn setup_pp_joint(
+    mut gentity_map: ResMut<GEntityMap>,
+    mut template_registry: ResMut<GEntityTemplateRegistry>,
+) {
+    gentity_map.add_entry(GEntityMapEntry::new(JointHook(GEntityJointKind::Hinge))
+        .matching(GEntityMatcher::prefix("hinge."))
+        .matching(GEntityMatcher::extras("gentity", "hinge")));
+    gentity_map.add_entry(GEntityMapEntry::new(JointHook(GEntityJointKind::Slider))
+        .matching(GEntityMatcher::prefix("slider."))
+        .matching(GEntityMatcher::extras("gentity", "slider")));
+    template_registry.register_with::<GEntityJoint>(|joint, entity_map| GEntityJoint {
+        gentity: map_entity(entity_map, joint.gentity),
+        name: joint.name.clone(),
+        kind: joint.kind,
+        body: map_entity(entity_map, joint.body),
+        axis: joint.axis,
+        rest_rotation: joint.rest_rotation,
+        rest_translation: joint.rest_translation,
+    });
+    template_registry.register_with::<GEntityJointMotor>(|motor, _| GEntityJointMotor {
+        target: motor.target,
+        stiffness: motor.stiffness,
+        damping: motor.damping,
+        max_force: motor.max_force,
+    });
+    template_registry.register_with::<RevoluteJoint>(|joint, entity_map| {
+        let mut joint = joint.clone();
+        joint.entity1 = map_entity(entity_map, joint.entity1);
+        joint.entity2 = map_entity(entity_map, joint.entity2);
+        joint
+    });
+    template_registry.register_with::<PrismaticJoint>(|joint, entity_map| {
+        let mut joint = joint.clone();
+        joint.entity1 = map_entity(entity_map, joint.entity1);
+        joint.entity2 = map_entity(entity_map, joint.entity2);
+        joint
+    });
+    template_registry.register::<RigidBody>();
+    template_registry.register_with::<ExternalForce>(|_, _| ExternalForce::default().with_persistence(false));
+    template_registry.register_with::<ExternalTorque>(|_, _| ExternalTorque::default().with_persistence(false));
+}
+
+pub fn set_joint_targets(
+    mut requests: EventReader<GEntityJointTarget>,
+    mut motors: Query<(&GEntityJoint, &mut GEntityJointMotor)>,
+) {
+    for request in requests.read() {
+        let motor = motors.iter_mut().find(|(joint, _)| joint.gentity == request.gentity && joint.name == request.joint);
+        let Some((joint, mut motor)) = motor else {
+            warn!("GEntity {:?} has no motorized joint '{}'", request.gentity, request.joint);
+            continue;
+        };
+        motor.target = match joint.kind {
+            GEntityJointKind::Hinge => request.target.to_radians(),
+            GEntityJointKind::Slider => request.target,
+        };
+    }
+}
+
+/// Pushes the bodies of motorized joints towards their targets, and the GEntity root the opposite
+/// way so the motors move the parts of a GEntity but not the GEntity as a whole.
+pub fn drive_joint_motors(
+    motors: Query<(&GEntityJoint, &GEntityJointMotor)>,
+    bodies: Query<(&Position, &Rotation, &LinearVelocity, &AngularVelocity, Option<&CenterOfMass>)>,
+    mut efforts: Query<(&mut ExternalForce, &mut ExternalTorque)>,
+) {
+    for (joint, motor) in motors.iter() {
+        let Ok([(root_position, root_rotation, root_linear, root_angular, root_center_of_mass), (body_position, body_rotation, body_linear, body_angular, body_center_of_mass)]) = bodies.get_many([joint.gentity, joint.body]) else {
+            continue;
+        };
+        let axis = root_rotation.0 * joint.axis;
+        let (position, velocity) = match joint.kind {
+            GEntityJointKind::Hinge => {
+                let relative = root_rotation.0.inverse() * body_rotation.0 * joint.rest_rotation.inverse();
+                let twist = 2.0 * Vector::new(relative.x, relative.y, relative.z).dot(joint.axis).atan2(relative.w);
+                let angle = (twist + std::f64::consts::PI).rem_euclid(std::f64::consts::TAU) - std::f64::consts::PI;
+                (angle, (body_angular.0 - root_angular.0).dot(axis))
+            }
+            GEntityJointKind::Slider => {
+                let offset = root_rotation.0.inverse() * (body_position.0 - root_position.0) - joint.rest_translation;
+                (offset.dot(joint.axis), (body_linear.0 - root_linear.0).dot(axis))
+            }
+        };
+        let effort = (motor.stiffness * (motor.target - position) - motor.damping * velocity)
+            .clamp(-motor.max_force, motor.max_force);
+        let Ok([(mut body_force, mut body_torque), (mut root_force, mut root_torque)]) = efforts.get_many_mut([joint.body, joint.gentity]) else {
+            continue;
+        };
+        match joint.kind {
+            GEntityJointKind::Hinge => {
+                body_torque.apply_torque(axis * effort);
+                root_torque.apply_torque(-axis * effort);
+            }
+            GEntityJointKind::Slider => {
+                // The reaction acts on the line of the force, through the center of mass of the body,
+                // so it does not spin the GEntity either.
+                let center_of_mass = |rotation: &Rotation, center_of_mass: Option<&CenterOfMass>| {
+                    rotation.rotate(center_of_mass.map_or(Vector::ZERO, |center_of_mass| center_of_mass.0))
+                };
+                let point = body_position.0 + center_of_mass(body_rotation, body_center_of_mass) - root_position.0;
+                body_force.apply_force(axis * effort);
+                root_force.apply_force_at_point(-axis * effort, point, center_of_mass(root_rotation, root_center_of_mass));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use bevy::time::TimeUpdateStrategy;
+    use super::*;
+
+    /// A GEntity root with a motorized joint, set up like [JointHook] does, and no gravity.
+    fn motor_app(kind: GEntityJointKind) -> App {
+        let mut app = App::new();
+        app
+            .add_plugins((MinimalPlugins, TransformPlugin, HierarchyPlugin, PhysicsPlugins::default()))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(1.0 / 60.0)))
+            .insert_resource(Gravity(Vector::ZERO))
+            .add_systems(Update, drive_joint_motors);
+        let efforts = || (
+            ExternalForce::default().with_persistence(false),
+            ExternalTorque::default().with_persistence(false),
+        );
+        let gentity = app.world.spawn((
+            RigidBody::Dynamic,
+            Collider::cuboid(4.0, 1.0, 2.0),
+            TransformBundle::default(),
+            efforts(),
+        )).id();
+        let rest_translation = Vector::new(3.0, 0.5, 0.0);
+        let body = app.world.spawn((
+            RigidBody::Dynamic,
+            Collider::cuboid(2.0, 0.5, 0.5),
+            TransformBundle::from_transform(Transform::from_translation(rest_translation.as_vec3())),
+            efforts(),
+        )).id();
+        let axis = Vector::Y;
+        let anchor = Vector::new(2.0, 0.5, 0.0);
+        let mut joint = match kind {
+            GEntityJointKind::Hinge => app.world.spawn(RevoluteJoint::new(gentity, body)
+                .with_local_anchor_1(anchor)
+                .with_local_anchor_2(anchor - rest_translation)
+                .with_aligned_axis(axis)),
+            GEntityJointKind::Slider => app.world.spawn(PrismaticJoint::new(gentity, body)
+                .with_local_anchor_1(anchor)
+                .with_local_anchor_2(anchor - rest_translation)
+                .with_free_axis(Vector::X)),
+        };
+        joint.insert((
+            GEntityJoint {
+                gentity,
+                name: "motor".into(),
+                kind,
+                body,
+                axis: if kind == GEntityJointKind::Hinge { axis } else { Vector::X },
+                rest_rotation: Quaternion::IDENTITY,
+                rest_translation,
+            },
+            GEntityJointMotor {
+                target: 1.0,
+                stiffness: DEFAULT_STIFFNESS,
+                damping: DEFAULT_DAMPING,
+                max_force: DEFAULT_MAX_FORCE,
+            },
+        ));
+        app
+    }
+
+    /// The total linear and angular momentum (around the origin) of all bodies.
+    fn momentum(app: &mut App) -> (Vector, Vector) {
+        let mut bodies = app.world.query::<(&Position, &Rotation, &LinearVelocity, &AngularVelocity, &Mass, &Inertia, &CenterOfMass)>();
+        bodies.iter(&app.world).fold((Vector::ZERO, Vector::ZERO), |(linear, angular), (position, rotation, linear_velocity, angular_velocity, mass, inertia, center_of_mass)| {
+            let momentum = linear_velocity.0 * mass.0;
+            let center = position.0 + rotation.rotate(center_of_mass.0);
+            (linear + momentum, angular + center.cross(momentum) + inertia.rotated(rotation).0 * angular_velocity.0)
+        })
+    }
+
+    fn assert_momentum_conserved(kind: GEntityJointKind) {
+        let mut app = motor_app(kind);
+        let mut moved = false;
+        for frame in 1..=60 {
+            app.update();
+            // Without the reaction on the root, the momentum grows by up to the maximum motor effort
+            // every second.
+            let tolerance = 1e-4 * DEFAULT_MAX_FORCE * frame as Scalar / 60.0;
+            let (linear, angular) = momentum(&mut app);
+            assert!(linear.length() < tolerance, "{:?} motor changed the linear momentum to {} in frame {}", kind, linear, frame);
+            assert!(angular.length() < tolerance, "{:?} motor changed the angular momentum to {} in frame {}", kind, angular, frame);
+            let mut velocities = app.world.query::<(&LinearVelocity, &AngularVelocity)>();
+            moved |= velocities.iter(&app.world).any(|(linear, angular)| linear.0 != Vector::ZERO || angular.0 != Vector::ZERO);
+        }
+        assert!(moved, "{:?} motor did not move anything", kind);
+    }
+
+    #[test]
+    fn hinge_motor_conserves_momentum() {
+        assert_momentum_conserved(GEntityJointKind::Hinge);
+    }
+
+    #[test]
+    fn slider_motor_conserves_momentum() {
+        assert_momentum_conserved(GEntityJointKind::Slider);
+    }
+}
@@ -0,0 +1,147 @@
+use bevy::prelude::*;
+use bevy_xpbd_3d::math::Vector;
+use bevy_xpbd_3d::prelude::*;
+use crate::gentity::gltf::hook::{GEntityHook, GEntityHookContext, GEntityHookError, GEntityMap, GEntityMapEntry, GEntityMatcher};
+use crate::gentity::gltf::pp_interact::{GEntityNodeActivated, INTERACTION_KEY};
+use crate::gentity::gltf::shape::build_collider;
+use crate::gentity::script::runtime::GEntityScriptCall;
+use crate::gentity::template::GEntityTemplateRegistry;
+use crate::player::Player;
+
+/// A pilot or crew station, entered by looking at it and pressing the interaction key.
+#[derive(Component)]
+pub struct GEntitySeat {
+    pub gentity: Entity,
+    pub name: String,
+    /// Whether the occupant flies the GEntity, set by the `pilot` custom property or a name starting with `pilot`.
+    pub pilot: bool,
+    pub occupant: Option<Entity>,
+}
+
+/// Placed on a player sitting in the given seat.
+#[derive(Component)]
+pub struct GEntitySeated(pub Entity);
+
+#[derive(Event)]
+pub struct GEntitySeatEntered {
+    pub gentity: Entity,
+    pub seat: Entity,
+    pub name: String,
+    pub occupant: Entity,
+}
+
+#[derive(Event)]
+pub struct GEntitySeatExited {
+    pub gentity: Entity,
+    pub seat: Entity,
+    pub name: String,
+    pub occupant: Entity,
+}
+
+pub struct SeatHook;
+
+impl GEntityHook for SeatHook {
+    fn run(&self, context: &GEntityHookContext, cmds: &mut Commands) -> Result<(), GEntityHookError> {
+        let transform = context.node_transform()?;
+        let collider = build_collider(context)?;
+        let mut cloned_transform = transform.clone();
+        cloned_transform.scale = Vec3::ONE;
+        cmds.entity(context.node.id())
+            .remove::<Transform>()
+            .insert(cloned_transform)
+            .insert(collider)
+            .insert(Sensor)
+            .insert(GEntitySeat {
+                gentity: context.gentity.id(),
+                name: context.name.to_string(),
+                pilot: context.extras.get_bool("pilot").unwrap_or_else(|| context.name.starts_with("pilot")),
+                occupant: None,
+            })
+        ;
+        Ok(())
+    }
+}
+
+pub fn setup_pp_seat(
+    mut gentity_map: ResMut<GEntityMap>,
+    mut template_registry: ResMut<GEntityTemplateRegistry>,
+) {
+    gentity_map.add_entry(GEntityMapEntry::new(SeatHook)
+        .matching(GEntityMatcher::prefix("seat."))
+        .matching(GEntityMatcher::extras("gentity", "seat")));
+    template_registry.register_with::<GEntitySeat>(|seat, entity_map| GEntitySeat {
+        gentity: entity_map.get(&seat.gentity).copied().unwrap_or(seat.gentity),
+        name: seat.name.clone(),
+        pilot: seat.pilot,
+        occupant: None,
+    });
+}
+
+pub fn enter_activated_seats(
+    mut activated_events: EventReader<GEntityNodeActivated>,
+    mut seats: Query<&mut GEntitySeat>,
+    mut entered_events: EventWriter<GEntitySeatEntered>,
+    mut script_calls: EventWriter<GEntityScriptCall>,
+    mut cmds: Commands,
+) {
+    for activated in activated_events.read() {
+        let Ok(mut seat) = seats.get_mut(activated.node) else {
+            continue;
+        };
+        if seat.occupant.is_some() {
+            continue;
+        }
+        seat.occupant = Some(activated.player);
+        cmds.entity(activated.player).insert(GEntitySeated(activated.node));
+        entered_events.send(GEntitySeatEntered {
+            gentity: seat.gentity,
+            seat: activated.node,
+            name: seat.name.clone(),
+            occupant: activated.player,
+        });
+        script_calls.send(GEntityScriptCall::new(seat.gentity, "onSeatEntered", vec![seat.name.clone().into()]));
+    }
+}
+
+pub fn exit_seats(
+    keyboard_input: Res<Input<KeyCode>>,
+    seated: Query<(Entity, &GEntitySeated)>,
+    mut seats: Query<&mut GEntitySeat>,
+    mut exited_events: EventWriter<GEntitySeatExited>,
+    mut script_calls: EventWriter<GEntityScriptCall>,
+    mut cmds: Commands,
+) {
+    for (occupant, GEntitySeated(seat_entity)) in seated.iter() {
+        let Ok(mut seat) = seats.get_mut(*seat_entity) else {
+            // The seat is gone together with its GEntity.
+            cmds.entity(occupant).remove::<GEntitySeated>();
+            continue;
+        };
+        if !keyboard_input.just_pressed(INTERACTION_KEY) {
+            continue;
+        }
+        seat.occupant = None;
+        cmds.entity(occupant).remove::<GEntitySeated>();
+        exited_events.send(GEntitySeatExited {
+            gentity: seat.gentity,
+            seat: *seat_entity,
+            name: seat.name.clone(),
+            occupant,
+        });
+        script_calls.send(GEntityScriptCall::new(seat.gentity, "onSeatExited", vec![seat.name.clone().into()]));
+    }
+}
+
+/// Keeps seated players at their seat while the GEntity moves.
+pub fn keep_players_in_seats(
+    mut players: Query<(&GEntitySeated, &GlobalTransform, &mut Position, &mut LinearVelocity), With<Player>>,
+    seats: Query<&GlobalTransform, With<GEntitySeat>>,
+) {
+    for (GEntitySeated(seat), player_transform, mut position, mut linear_velocity) in players.iter_mut() {
+        let Ok(seat_transform) = seats.get(*seat) else {
+            continue;
+        };
+        position.0 += (seat_transform.translation() - player_transform.translation()).as_dvec3();
+        linear_velocity.0 = Vector::ZERO;
+    }
+}
@@ -0,0 +1,83 @@
+use bevy::prelude::*;
+use bevy_xpbd_3d::math::Vector;
+use bevy_xpbd_3d::prelude::*;
+use crate::gentity::gltf::hook::{GEntityHook, GEntityHookContext, GEntityHookError, GEntityMap, GEntityMapEntry, GEntityMatcher};
+use crate::gentity::script::runtime::GEntityScriptCall;
+use crate::gentity::template::GEntityTemplateRegistry;
+use crate::player::Player;
+
+/// A location players can be spawned at, eg. a bunk or an airlock.
+#[derive(Component)]
+pub struct GEntitySpawnPoint {
+    pub gentity: Entity,
+    pub name: String,
+}
+
+/// Moves the player to a spawn point of `gentity`, the first one found if `name` is `None`.
+#[derive(Event)]
+pub struct GEntitySpawnPlayer {
+    pub gentity: Entity,
+    pub name: Option<String>,
+}
+
+#[derive(Event)]
+pub struct GEntityPlayerSpawned {
+    pub gentity: Entity,
+    pub spawn_point: Entity,
+    pub name: String,
+}
+
+pub struct SpawnPointHook;
+
+impl GEntityHook for SpawnPointHook {
+    fn run(&self, context: &GEntityHookContext, cmds: &mut Commands) -> Result<(), GEntityHookError> {
+        cmds.entity(context.node.id()).insert(GEntitySpawnPoint {
+            gentity: context.gentity.id(),
+            name: context.name.to_string(),
+        });
+        Ok(())
+    }
+}
+
+pub fn setup_pp_spawn(
+    mut gentity_map: ResMut<GEntityMap>,
+    mut template_registry: ResMut<GEntityTemplateRegistry>,
+) {
+    gentity_map.add_entry(GEntityMapEntry::new(SpawnPointHook)
+        .matching(GEntityMatcher::prefix("spawn."))
+        .matching(GEntityMatcher::extras("gentity", "spawn")));
+    template_registry.register_with::<GEntitySpawnPoint>(|spawn_point, entity_map| GEntitySpawnPoint {
+        gentity: entity_map.get(&spawn_point.gentity).copied().unwrap_or(spawn_point.gentity),
+        name: spawn_point.name.clone(),
+    });
+}
+
+pub fn spawn_player_at_spawn_points(
+    mut requests: EventReader<GEntitySpawnPlayer>,
+    spawn_points: Query<(Entity, &GEntitySpawnPoint, &GlobalTransform)>,
+    mut players: Query<(&GlobalTransform, &mut Position, &mut LinearVelocity), With<Player>>,
+    mut spawned_events: EventWriter<GEntityPlayerSpawned>,
+    mut script_calls: EventWriter<GEntityScriptCall>,
+) {
+    for request in requests.read() {
+        let spawn_point = spawn_points.iter().find(|(_, spawn_point, _)| {
+            spawn_point.gentity == request.gentity && request.name.as_ref().map_or(true, |name| *name == spawn_point.name)
+        });
+        let Some((spawn_point_entity, spawn_point, spawn_point_transform)) = spawn_point else {
+            warn!("GEntity {:?} has no spawn point {:?}", request.gentity, request.name);
+            continue;
+        };
+        for (player_transform, mut position, mut linear_velocity) in players.iter_mut() {
+            // Both global transforms are relative to the floating origin, so their difference is
+            // valid in physics space too.
+            position.0 += (spawn_point_transform.translation() - player_transform.translation()).as_dvec3();
+            linear_velocity.0 = Vector::ZERO;
+        }
+        spawned_events.send(GEntityPlayerSpawned {
+            gentity: spawn_point.gentity,
+            spawn_point: spawn_point_entity,
+            name: spawn_point.name.clone(),
+        });
+        script_calls.send(GEntityScriptCall::new(spawn_point.gentity, "onPlayerSpawned", vec![spawn_point.name.clone().into()]));
+    }
+}
@@ -0,0 +1,164 @@
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_xpbd_3d::math::{Scalar, Vector};
+use bevy_xpbd_3d::prelude::*;
+use crate::gentity::gltf::hook::{GEntityHook, GEntityHookContext, GEntityHookError, GEntityMap, GEntityMapEntry, GEntityMatcher};
+use crate::gentity::gltf::pp_seat::{GEntitySeat, GEntitySeated};
+use crate::gentity::template::GEntityTemplateRegistry;
+
+/// An engine pushing its GEntity along the forward axis (-Z) of the node.
+#[derive(Component)]
+pub struct GEntityThruster {
+    pub gentity: Entity,
+    pub name: String,
+    /// The force at full throttle, in newton.
+    pub max_thrust: Scalar,
+    /// 0 to 1, set by [allocate_thruster_throttle].
+    pub throttle: Scalar,
+}
+
+/// The movement the pilot of a GEntity asks for, in the body frame of the GEntity.
+/// Each axis ranges from -1 to 1.
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq)]
+pub struct GEntityFlightInput {
+    pub translation: DVec3,
+    /// Rotation around the X (pitch), Y (yaw) and Z (roll) axis.
+    pub rotation: DVec3,
+}
+
+pub struct ThrusterHook;
+
+impl GEntityHook for ThrusterHook {
+    fn run(&self, context: &GEntityHookContext, cmds: &mut Commands) -> Result<(), GEntityHookError> {
+        let max_thrust = match context.extras.get_f64("max_thrust") {
+            Some(max_thrust) if max_thrust.is_finite() && max_thrust > 0.0 => max_thrust,
+            Some(max_thrust) => return Err(GEntityHookError::InvalidExtrasValue {
+                key: "max_thrust".into(),
+                reason: format!("{} is not a positive force", max_thrust),
+            }),
+            None => return Err(GEntityHookError::InvalidExtrasValue {
+                key: "max_thrust".into(),
+                reason: "missing".into(),
+            }),
+        };
+        cmds.entity(context.node.id()).insert(GEntityThruster {
+            gentity: context.gentity.id(),
+            name: context.name.to_string(),
+            max_thrust,
+            throttle: 0.0,
+        });
+        Ok(())
+    }
+}
+
+pub fn setup_pp_thruster(
+    mut gentity_map: ResMut<GEntityMap>,
+    mut template_registry: ResMut<GEntityTemplateRegistry>,
+) {
+    gentity_map.add_entry(GEntityMapEntry::new(ThrusterHook)
+        .matching(GEntityMatcher::prefix("thruster."))
+        .matching(GEntityMatcher::extras("gentity", "thruster")));
+    template_registry.register_with::<GEntityThruster>(|thruster, entity_map| GEntityThruster {
+        gentity: entity_map.get(&thruster.gentity).copied().unwrap_or(thruster.gentity),
+        name: thruster.name.clone(),
+        max_thrust: thruster.max_thrust,
+        throttle: 0.0,
+    });
+}
+
+/// Reads the flight controls of players sitting in a pilot seat.
+///
+/// W/S move forward and backward, A/D sideways, Space/Left Shift up and down.
+/// The arrow keys pitch and yaw, Q/E roll.
+pub fn read_pilot_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    pilots: Query<&GEntitySeated>,
+    seats: Query<&GEntitySeat>,
+    thrusters: Query<&GEntityThruster>,
+    mut flight_inputs: Query<&mut GEntityFlightInput>,
+    mut cmds: Commands,
+) {
+    let axis = |positive: KeyCode, negative: KeyCode| {
+        keyboard_input.pressed(positive) as i8 as f64 - keyboard_input.pressed(negative) as i8 as f64
+    };
+    let input = GEntityFlightInput {
+        translation: DVec3::new(
+            axis(KeyCode::D, KeyCode::A),
+            axis(KeyCode::Space, KeyCode::ShiftLeft),
+            axis(KeyCode::S, KeyCode::W),
+        ),
+        rotation: DVec3::new(
+            axis(KeyCode::Up, KeyCode::Down),
+            axis(KeyCode::Left, KeyCode::Right),
+            axis(KeyCode::Q, KeyCode::E),
+        ),
+    };
+    let mut piloted = HashMap::new();
+    for GEntitySeated(seat) in pilots.iter() {
+        if let Ok(seat) = seats.get(*seat) {
+            if seat.pilot {
+                piloted.insert(seat.gentity, input);
+            }
+        }
+    }
+    let mut missing = vec![];
+    for thruster in thrusters.iter() {
+        let input = piloted.get(&thruster.gentity).copied().unwrap_or_default();
+        match flight_inputs.get_mut(thruster.gentity) {
+            Ok(mut flight_input) => {
+                if *flight_input != input {
+                    *flight_input = input;
+                }
+            }
+            Err(_) if !missing.contains(&thruster.gentity) => missing.push(thruster.gentity),
+            Err(_) => {}
+        }
+    }
+    for gentity in missing {
+        cmds.entity(gentity).try_insert((
+            GEntityFlightInput::default(),
+            ExternalForce::default().with_persistence(false),
+        ));
+    }
+}
+
+/// Sets the throttle of every thruster to how much its force and torque point along the
+/// requested flight input.
+pub fn allocate_thruster_throttle(
+    mut thrusters: Query<(&mut GEntityThruster, &GlobalTransform)>,
+    gentities: Query<(&GEntityFlightInput, &GlobalTransform, Option<&CenterOfMass>)>,
+) {
+    for (mut thruster, thruster_transform) in thrusters.iter_mut() {
+        let Ok((input, gentity_transform, center_of_mass)) = gentities.get(thruster.gentity) else {
+            continue;
+        };
+        let local = thruster_transform.reparented_to(gentity_transform);
+        let direction = local.forward().as_dvec3();
+        let arm = local.translation.as_dvec3() - center_of_mass.map_or(Vector::ZERO, |center_of_mass| center_of_mass.0);
+        let torque = arm.cross(direction).normalize_or_zero();
+        let throttle = (direction.dot(input.translation) + torque.dot(input.rotation)).clamp(0.0, 1.0);
+        if thruster.throttle != throttle {
+            thruster.throttle = throttle;
+        }
+    }
+}
+
+pub fn apply_thruster_forces(
+    thrusters: Query<(&GEntityThruster, &GlobalTransform)>,
+    mut gentities: Query<(&mut ExternalForce, &GlobalTransform, &Rotation, Option<&CenterOfMass>)>,
+) {
+    for (thruster, thruster_transform) in thrusters.iter() {
+        if thruster.throttle <= 0.0 {
+            continue;
+        }
+        let Ok((mut external_force, gentity_transform, rotation, center_of_mass)) = gentities.get_mut(thruster.gentity) else {
+            continue;
+        };
+        let local = thruster_transform.reparented_to(gentity_transform);
+        let force = rotation.rotate(local.forward().as_dvec3() * thruster.max_thrust * thruster.throttle);
+        let point = rotation.rotate(local.translation.as_dvec3());
+        let center_of_mass = rotation.rotate(center_of_mass.map_or(Vector::ZERO, |center_of_mass| center_of_mass.0));
+        external_force.apply_force_at_point(force, point, center_of_mass);
+    }
+}
@@ -1,26 +1,67 @@
 use bevy::prelude::*;
 use bevy_xpbd_3d::prelude::*;
-use crate::gentity::gltf::hook::GEntityMap;
+use crate::gentity::gltf::shape::build_collider;
+use crate::gentity::gltf::hook::{GEntityHook, GEntityHookContext, GEntityHookError, GEntityMap, GEntityMapEntry, GEntityMatcher};
+use crate::gentity::script::runtime::GEntityScriptCall;
+use crate::gentity::template::GEntityTemplateRegistry;
+
+#[derive(Component)]
+pub struct GEntityTrigger {
+    pub gentity: Entity,
+    pub name: String,
+}
+
+pub struct TriggerHook;
+
+impl GEntityHook for TriggerHook {
+    fn run(&self, context: &GEntityHookContext, cmds: &mut Commands) -> Result<(), GEntityHookError> {
+        let transform = context.node_transform()?;
+        let collider = build_collider(context)?;
+        let mut cloned_transform = transform.clone();
+        cloned_transform.scale = Vec3::ONE;
+        let mut node_cmds = cmds.entity(context.node.id());
+        node_cmds
+            .remove::<Transform>()
+            .insert(cloned_transform)
+            .insert(collider)
+            .insert(Sensor)
+            .insert(GEntityTrigger {
+                gentity: context.gentity.id(),
+                name: context.name.to_string(),
+            })
+        ;
+        if let Some(collision_layers) = context.toml_asset().and_then(|toml_asset| toml_asset.physics.collision_layers()) {
+            node_cmds.insert(collision_layers);
+        }
+        Ok(())
+    }
+}
 
 pub fn setup_pp_trigger(
-    mut gentity_map: ResMut<GEntityMap>
+    mut gentity_map: ResMut<GEntityMap>,
+    mut template_registry: ResMut<GEntityTemplateRegistry>,
 ) {
-    gentity_map.add("trigger.".into(), false, Box::new(|parent, entity, cmds| {
-        let transform_opt = entity.get::<Transform>();
-        if let Some(transform) = transform_opt {
-            let mut cloned_transform = transform.clone();
-            cloned_transform.scale = Vec3::ONE;
-            cmds
-                .entity(entity.id())
-                .remove::<Transform>()
-                .insert(cloned_transform)
-                .insert(Collider::cuboid(transform.scale.x as f64, transform.scale.y as f64, transform.scale.z as f64))
-                .insert(Sensor)
-            ;
-        } else {
-            warn!("No transform found for entity: {:?}", entity.id());
+    template_registry.register_with::<GEntityTrigger>(|trigger, entity_map| GEntityTrigger {
+        gentity: entity_map.get(&trigger.gentity).copied().unwrap_or(trigger.gentity),
+        name: trigger.name.clone(),
+    });
+    gentity_map.add_entry(GEntityMapEntry::new(TriggerHook)
+        .matching(GEntityMatcher::prefix("trigger."))
+        .matching(GEntityMatcher::extras("gentity", "trigger")));
+}
+
+pub fn call_trigger_scripts(
+    mut collision_started_event_reader: EventReader<CollisionStarted>,
+    triggers: Query<&GEntityTrigger>,
+    mut script_calls: EventWriter<GEntityScriptCall>,
+) {
+    for CollisionStarted(entity1, entity2) in collision_started_event_reader.read() {
+        for entity in [entity1, entity2] {
+            if let Ok(trigger) = triggers.get(*entity) {
+                script_calls.send(GEntityScriptCall::new(trigger.gentity, "onTrigger", vec![trigger.name.clone().into()]));
+            }
         }
-    }));
+    }
 }
 
 pub fn print_collisions(
@@ -0,0 +1,160 @@
+use bevy::prelude::*;
+use bevy_xpbd_3d::math::Vector;
+use bevy_xpbd_3d::prelude::*;
+use crate::gentity::gltf::hook::{GEntityHookContext, GEntityHookError, GEntityNodeExtras};
+
+/// The collider shapes gltf nodes can be turned into.
+///
+/// Primitive shapes are sized by the node scale, which is the size of the shape along each axis.
+/// Convex hulls and trimeshes are built from the mesh of the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GEntityColliderShape {
+    Cuboid,
+    Sphere,
+    Capsule,
+    Cylinder,
+    Cone,
+    ConvexHull,
+    TriMesh,
+}
+
+impl GEntityColliderShape {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "cuboid" => Some(Self::Cuboid),
+            "sphere" => Some(Self::Sphere),
+            "capsule" => Some(Self::Capsule),
+            "cylinder" => Some(Self::Cylinder),
+            "cone" => Some(Self::Cone),
+            "convex" => Some(Self::ConvexHull),
+            "trimesh" => Some(Self::TriMesh),
+            _ => None,
+        }
+    }
+
+    /// Reads the shape of the node of `context`, see [GEntityColliderShape::from_node].
+    pub fn from_context(context: &GEntityHookContext) -> Result<Self, GEntityHookError> {
+        Self::from_node(&context.extras, context.name)
+    }
+
+    /// Reads the shape from the `shape` extras value or, failing that, from the first segment of
+    /// the node name if another one follows (eg. `sphere.top` of `collider.sphere.top`). Nodes
+    /// naming no shape are cuboids, so `collider.convex` is a cuboid named `convex`.
+    pub fn from_node(extras: &GEntityNodeExtras, name: &str) -> Result<Self, GEntityHookError> {
+        if let Some(shape) = extras.get_str("shape") {
+            return Self::parse(shape).ok_or_else(|| GEntityHookError::InvalidExtrasValue {
+                key: "shape".into(),
+                reason: format!("unknown shape '{}'", shape),
+            });
+        }
+        let shape = match name.split_once('.') {
+            Some((segment, _)) => Self::parse(segment),
+            None => None,
+        };
+        Ok(shape.unwrap_or(Self::Cuboid))
+    }
+}
+
+/// Builds the collider for the node of `context`, reading the mesh data of the node and its
+/// primitives before the hook system strips them.
+pub fn build_collider(context: &GEntityHookContext) -> Result<Collider, GEntityHookError> {
+    let scale = context.node_transform()?.scale.as_dvec3();
+    let collider = match GEntityColliderShape::from_context(context)? {
+        GEntityColliderShape::Cuboid => Collider::cuboid(scale.x, scale.y, scale.z),
+        GEntityColliderShape::Sphere => Collider::ball(scale.max_element() / 2.0),
+        GEntityColliderShape::Capsule => {
+            let radius = scale.x.max(scale.z) / 2.0;
+            Collider::capsule((scale.y - 2.0 * radius).max(0.0), radius)
+        }
+        GEntityColliderShape::Cylinder => Collider::cylinder(scale.y, scale.x.max(scale.z) / 2.0),
+        GEntityColliderShape::Cone => Collider::cone(scale.y, scale.x.max(scale.z) / 2.0),
+        GEntityColliderShape::ConvexHull => {
+            let (vertices, _) = read_node_mesh(context, scale)?;
+            match Collider::convex_hull(vertices) {
+                Some(collider) => collider,
+                None => return Err(GEntityHookError::Other("Failed computing convex hull of node mesh".into())),
+            }
+        }
+        GEntityColliderShape::TriMesh => {
+            let (vertices, indices) = read_node_mesh(context, scale)?;
+            Collider::trimesh(vertices, indices)
+        }
+    };
+    Ok(collider)
+}
+
+/// Collects the scaled vertices and triangles of all mesh primitives of the node.
+fn read_node_mesh(context: &GEntityHookContext, scale: Vector) -> Result<(Vec<Vector>, Vec<[u32; 3]>), GEntityHookError> {
+    let meshes = context.world.resource::<Assets<Mesh>>();
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    let primitives = context.node.get::<Children>()
+        .map(|children| children.iter().copied().collect::<Vec<_>>())
+        .unwrap_or_default();
+    for entity in std::iter::once(context.node.id()).chain(primitives) {
+        let Some(handle) = context.world.get_entity(entity).and_then(|entity| entity.get::<Handle<Mesh>>()) else {
+            continue;
+        };
+        let Some(mesh) = meshes.get(handle) else {
+            continue;
+        };
+        let Some(positions) = mesh.attribute(Mesh::ATTRIBUTE_POSITION).and_then(|positions| positions.as_float3()) else {
+            continue;
+        };
+        let offset = vertices.len() as u32;
+        vertices.extend(positions.iter().map(|position| Vec3::from_array(*position).as_dvec3() * scale));
+        match mesh.indices() {
+            Some(mesh_indices) => {
+                let mesh_indices = mesh_indices.iter().map(|index| index as u32 + offset).collect::<Vec<_>>();
+                indices.extend(mesh_indices.chunks_exact(3).map(|triangle| [triangle[0], triangle[1], triangle[2]]));
+            }
+            None => {
+                indices.extend((0..positions.len() as u32 / 3).map(|triangle| {
+                    let first = offset + triangle * 3;
+                    [first, first + 1, first + 2]
+                }));
+            }
+        }
+    }
+    if vertices.is_empty() {
+        return Err(GEntityHookError::MissingComponent(context.node.id(), "Handle<Mesh>"));
+    }
+    Ok((vertices, indices))
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::gltf::GltfExtras;
+    use super::*;
+
+    fn shape_of(name: &str) -> GEntityColliderShape {
+        GEntityColliderShape::from_node(&GEntityNodeExtras::default(), name).unwrap()
+    }
+
+    #[test]
+    fn shapes_are_named_before_another_segment() {
+        assert_eq!(shape_of("sphere.nose"), GEntityColliderShape::Sphere);
+        assert_eq!(shape_of("convex.hull"), GEntityColliderShape::ConvexHull);
+        assert_eq!(shape_of("trimesh.hangar.001"), GEntityColliderShape::TriMesh);
+        assert_eq!(shape_of("sphere"), GEntityColliderShape::Cuboid);
+        assert_eq!(shape_of("nose"), GEntityColliderShape::Cuboid);
+    }
+
+    #[test]
+    fn ordinary_names_stay_cuboids() {
+        for name in ["hull", "mesh", "box", "ball", "hull.front", "mesh.floor", "box.cargo", "ball.turret"] {
+            assert_eq!(shape_of(name), GEntityColliderShape::Cuboid, "{}", name);
+        }
+    }
+
+    #[test]
+    fn extras_override_the_name() {
+        let extras = GEntityNodeExtras::parse(Some(&GltfExtras { value: r#"{"shape": "capsule"}"#.into() })).unwrap();
+        assert_eq!(GEntityColliderShape::from_node(&extras, "sphere.nose").unwrap(), GEntityColliderShape::Capsule);
+        let extras = GEntityNodeExtras::parse(Some(&GltfExtras { value: r#"{"shape": "hull"}"#.into() })).unwrap();
+        assert!(matches!(
+            GEntityColliderShape::from_node(&extras, "nose"),
+            Err(GEntityHookError::InvalidExtrasValue { .. }),
+        ));
+    }
+}
@@ -2,10 +2,22 @@
 use bevy::prelude::*;
 use big_space::GridCell;
 use crate::gentity::asset_loaders;
+use crate::gentity::despawn::*;
 use crate::gentity::gltf::hook::*;
 use crate::gentity::gltf::pp_collision::*;
 use crate::gentity::gltf::pp_trigger::*;
+use crate::gentity::gltf::pp_spawn::*;
+use crate::gentity::gltf::pp_seat::*;
+use crate::gentity::gltf::pp_interact::*;
+use crate::gentity::gltf::pp_thruster::*;
+use crate::gentity::gltf::pp_dock::*;
+use crate::gentity::gltf::pp_gravity::*;
+use crate::gentity::gltf::pp_atmosphere::*;
+use crate::gentity::gltf::pp_joint::*;
 use crate::gentity::asset_loaders::toml_asset_loader::*;
+use crate::gentity::script::runtime::*;
+use crate::gentity::state::*;
+use crate::gentity::template::*;
 
 
 #[derive(Default, Component)]
@@ -17,6 +29,7 @@ pub struct GEntityBundle {
     pub transform: Transform,
     pub grid_cell: GridCell<i64>,
     pub init: GEntityInitializeFromTomlComponent,
+    pub state: GEntityState,
 }
 pub struct GEntityPlugin;
 
@@ -33,9 +46,75 @@ fn build(&self, app: &mut App) {
             .add_systems(Update, process_gentity_toml_file.run_if(any_with_component::<GEntityInitializeFromTomlComponent>()))
             // pp_collision
             .add_systems(Startup, setup_pp_collision)
+            .add_systems(Update, configure_gentity_mass.run_if(any_with_component::<GEntityMassPending>()))
             // pp_trigger
             .add_systems(Startup, setup_pp_trigger)
             .add_systems(Update, print_collisions)
+            .add_systems(Update, call_trigger_scripts)
+            // pp_spawn
+            .add_event::<GEntitySpawnPlayer>()
+            .add_event::<GEntityPlayerSpawned>()
+            .add_systems(Startup, setup_pp_spawn)
+            .add_systems(Update, spawn_player_at_spawn_points)
+            // pp_seat, pp_interact
+            .add_event::<GEntityNodeActivated>()
+            .add_event::<GEntityInteracted>()
+            .add_event::<GEntitySeatEntered>()
+            .add_event::<GEntitySeatExited>()
+            .add_systems(Startup, (setup_pp_seat, setup_pp_interact))
+            .add_systems(Update, (
+                exit_seats,
+                activate_looked_at_nodes,
+                (interact_with_activated_nodes, enter_activated_seats),
+                keep_players_in_seats,
+            ).chain())
+            // pp_thruster
+            .add_systems(Startup, setup_pp_thruster)
+            .add_systems(Update, (
+                read_pilot_input,
+                allocate_thruster_throttle,
+                apply_thruster_forces,
+            ).chain().after(keep_players_in_seats))
+            // pp_dock
+            .add_event::<GEntityDocked>()
+            .add_event::<GEntityUndock>()
+            .add_event::<GEntityUndocked>()
+            .add_systems(Startup, setup_pp_dock)
+            .add_systems(Update, (dock_aligned_ports, undock_requested_ports).chain())
+            // pp_gravity
+            .add_systems(Startup, setup_pp_gravity)
+            // pp_atmosphere
+            .add_event::<GEntityAtmosphereRequest>()
+            .add_systems(Startup, setup_pp_atmosphere)
+            // pp_joint
+            .add_event::<GEntityJointTarget>()
+            .add_systems(Startup, setup_pp_joint)
+            .add_systems(Update, (set_joint_targets, drive_joint_motors).chain())
+            // state
+            .add_event::<GEntityReady>()
+            .add_event::<GEntityFailed>()
+            .add_systems(Startup, setup_gentity_placeholder_assets)
+            .add_systems(Update, (detect_failed_gentity_loads, finish_gentity_processing))
+            // template
+            .init_resource::<GEntityTemplateRegistry>()
+            .init_resource::<GEntityTemplates>()
+            .add_systems(Update, capture_gentity_templates
+                .after(finish_gentity_processing)
+                .run_if(any_with_component::<GEntityTemplateSource>()))
+            .add_systems(Update, invalidate_gentity_templates)
+            // despawn
+            .add_event::<DespawnGEntity>()
+            .add_event::<GEntityDespawned>()
+            .add_systems(Update, despawn_gentities.before(run_gentity_script_calls))
+            // script
+            .add_event::<GEntityScriptCall>()
+            .add_event::<GEntityNotification>()
+            .add_systems(Update, (
+                initialize_gentity_scripts,
+                run_gentity_script_calls,
+                apply_gentity_script_commands,
+                display_gentity_notifications,
+            ).chain())
         ;
     }
 }
@@ -0,0 +1,2 @@
+pub(crate) mod capabilities;
+pub(crate) mod runtime;
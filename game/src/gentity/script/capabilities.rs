@@ -0,0 +1,97 @@
+use std::fmt::{Display, Formatter};
+
+/// A group of Rhai functions a package has to declare in its `config.toml` before its scripts
+/// may use them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ScriptCapability {
+    Physics,
+    Spawn,
+    Messaging,
+    Ui,
+    Animation,
+    Atmosphere,
+}
+
+impl ScriptCapability {
+    pub const ALL: [ScriptCapability; 6] = [
+        ScriptCapability::Physics,
+        ScriptCapability::Spawn,
+        ScriptCapability::Messaging,
+        ScriptCapability::Ui,
+        ScriptCapability::Animation,
+        ScriptCapability::Atmosphere,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScriptCapability::Physics => "physics",
+            ScriptCapability::Spawn => "spawn",
+            ScriptCapability::Messaging => "messaging",
+            ScriptCapability::Ui => "ui",
+            ScriptCapability::Animation => "animation",
+            ScriptCapability::Atmosphere => "atmosphere",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|capability| capability.as_str() == value)
+    }
+
+    /// The names of the Rhai functions which are only registered if this capability is declared.
+    pub fn functions(&self) -> &'static [&'static str] {
+        match self {
+            ScriptCapability::Physics => &["applyImpulse", "setLinearVelocity", "setAngularVelocity", "undock", "setJointTarget"],
+            ScriptCapability::Spawn => &["spawn"],
+            ScriptCapability::Messaging => &["sendMessage"],
+            ScriptCapability::Ui => &["showNotification"],
+            ScriptCapability::Animation => &["playAnimation", "stopAnimation"],
+            ScriptCapability::Atmosphere => &["getPressure", "setPressure", "openDoor", "closeDoor"],
+        }
+    }
+
+    /// Finds the capability guarding the Rhai function with the given name.
+    pub fn of_function(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|capability| capability.functions().contains(&name))
+    }
+}
+
+impl Display for ScriptCapability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The set of [ScriptCapability]s a package declared under `[scripts] capabilities`.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct ScriptCapabilities(Vec<ScriptCapability>);
+
+impl ScriptCapabilities {
+    pub fn new(capabilities: impl IntoIterator<Item = ScriptCapability>) -> Self {
+        let mut capabilities = capabilities.into_iter().collect::<Vec<_>>();
+        capabilities.sort();
+        capabilities.dedup();
+        Self(capabilities)
+    }
+
+    pub fn contains(&self, capability: ScriptCapability) -> bool {
+        self.0.contains(&capability)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ScriptCapability> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl Display for ScriptCapabilities {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("none");
+        }
+        let names = self.0.iter().map(|capability| capability.as_str()).collect::<Vec<_>>();
+        f.write_str(&names.join(", "))
+    }
+}
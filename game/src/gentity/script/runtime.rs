@@ -0,0 +1,496 @@
+use std::sync::{Arc, Mutex};
+use bevy::gltf::Gltf;
+use bevy::math::{DVec3, Vec3};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_xpbd_3d::prelude::*;
+use big_space::GridCell;
+use rhai::{Dynamic, Engine, EvalAltResult, ImmutableString, Scope, AST};
+use thiserror::Error;
+use crate::gentity::asset_loaders::rhai_asset_loader::RhaiScript;
+use crate::gentity::asset_loaders::toml_asset_loader::TomlAsset;
+use crate::gentity::gltf::pp_atmosphere::{AtmosphereRequest, GEntityAtmosphereRequest};
+use crate::gentity::gltf::pp_dock::GEntityUndock;
+use crate::gentity::gltf::pp_joint::GEntityJointTarget;
+use crate::gentity::plugin::{GEntityBundle, GEntityInitializeFromTomlComponent};
+use crate::gentity::script::capabilities::{ScriptCapabilities, ScriptCapability};
+use crate::gentity::state::GEntityState;
+use crate::localization::Localization;
+
+const NOTIFICATION_SECONDS: f32 = 5.0;
+
+/// Something a script asked the engine to do. Scripts never touch the [World] directly,
+/// their calls are queued and applied by [apply_gentity_script_commands].
+pub enum ScriptCommand {
+    ApplyImpulse(DVec3),
+    SetLinearVelocity(DVec3),
+    SetAngularVelocity(DVec3),
+    Undock(Option<String>),
+    SetJointTarget { joint: String, target: f64 },
+    Spawn { path: String, offset: Vec3 },
+    SendMessage { name: String, payload: Dynamic },
+    ShowNotification(String),
+    PlayAnimation(String),
+    StopAnimation,
+    SetPressure { volume: String, pressure: f64 },
+    SetDoor { door: String, open: bool },
+}
+
+type ScriptCommandQueue = Arc<Mutex<Vec<ScriptCommand>>>;
+
+/// What scripts can read about their GEntity, kept up to date by the systems owning the data.
+#[derive(Default)]
+pub struct ScriptSharedState {
+    /// The pressure of every atmosphere volume by name, in kPa.
+    pub pressures: HashMap<String, f64>,
+}
+
+pub type ScriptSharedStateHandle = Arc<Mutex<ScriptSharedState>>;
+
+/// Calls the script function `function` of the GEntity `entity`, if any of its scripts defines it.
+#[derive(Event)]
+pub struct GEntityScriptCall {
+    pub entity: Entity,
+    pub function: String,
+    pub args: Vec<Dynamic>,
+}
+
+impl GEntityScriptCall {
+    pub fn new(entity: Entity, function: impl Into<String>, args: Vec<Dynamic>) -> Self {
+        Self {
+            entity,
+            function: function.into(),
+            args,
+        }
+    }
+}
+
+#[derive(Event)]
+pub struct GEntityNotification {
+    pub entity: Entity,
+    pub text: String,
+}
+
+#[derive(Component)]
+pub struct GEntityNotificationTimer(Timer);
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum GEntityScriptError {
+    #[error("Failed compiling script '{path}': {message}")]
+    Compile { path: String, message: String },
+    #[error("Script of package '{package}' called '{function}' which requires the '{capability}' capability; declare it under [scripts] capabilities in config.toml")]
+    CapabilityNotDeclared { package: String, function: String, capability: ScriptCapability },
+    #[error("Script of package '{package}' failed: {message}")]
+    Runtime { package: String, message: String },
+}
+
+/// The scripts of a single GEntity, running in an engine that only knows the functions of the
+/// capabilities its package declared.
+#[derive(Component)]
+pub struct GEntityScripts {
+    package: String,
+    capabilities: ScriptCapabilities,
+    engine: Engine,
+    scope: Scope<'static>,
+    asts: Vec<AST>,
+    commands: ScriptCommandQueue,
+    shared_state: ScriptSharedStateHandle,
+}
+
+impl GEntityScripts {
+    pub fn new(package: String, capabilities: ScriptCapabilities) -> Self {
+        let commands: ScriptCommandQueue = Arc::new(Mutex::new(Vec::new()));
+        let shared_state: ScriptSharedStateHandle = Arc::new(Mutex::new(ScriptSharedState::default()));
+        let mut engine = Engine::new();
+        let print_package = package.clone();
+        engine.on_print(move |text| info!("[{}] {}", print_package, text));
+        for capability in capabilities.iter() {
+            register_capability(&mut engine, capability, &commands, &shared_state);
+        }
+        Self {
+            package,
+            capabilities,
+            engine,
+            scope: Scope::new(),
+            asts: vec![],
+            commands,
+            shared_state,
+        }
+    }
+
+    pub fn package(&self) -> &str {
+        &self.package
+    }
+
+    pub fn capabilities(&self) -> &ScriptCapabilities {
+        &self.capabilities
+    }
+
+    pub fn shared_state(&self) -> &ScriptSharedStateHandle {
+        &self.shared_state
+    }
+
+    /// Compiles the script and runs its top level statements.
+    pub fn load(&mut self, script: &RhaiScript) -> Result<(), GEntityScriptError> {
+        let ast = match self.engine.compile(&script.content) {
+            Ok(ast) => ast,
+            Err(error) => return Err(GEntityScriptError::Compile {
+                path: script.path.clone(),
+                message: error.to_string(),
+            }),
+        };
+        if let Err(error) = self.engine.run_ast_with_scope(&mut self.scope, &ast) {
+            return Err(self.to_error(&error));
+        }
+        self.asts.push(ast);
+        Ok(())
+    }
+
+    /// Calls `function` in every script defining it. Scripts not defining it are skipped.
+    pub fn call(&mut self, function: &str, args: Vec<Dynamic>) -> Result<(), GEntityScriptError> {
+        for ast in self.asts.iter() {
+            let defined = ast.iter_functions().any(|f| f.name == function && f.params.len() == args.len());
+            if !defined {
+                continue;
+            }
+            if let Err(error) = self.engine.call_fn::<Dynamic>(&mut self.scope, ast, function, args.clone()) {
+                return Err(self.to_error(&error));
+            }
+        }
+        Ok(())
+    }
+
+    fn take_commands(&self) -> Vec<ScriptCommand> {
+        match self.commands.lock() {
+            Ok(mut commands) => std::mem::take(&mut *commands),
+            Err(_) => vec![],
+        }
+    }
+
+    fn to_error(&self, error: &EvalAltResult) -> GEntityScriptError {
+        match undeclared_capability(error, &self.capabilities) {
+            Some((function, capability)) => GEntityScriptError::CapabilityNotDeclared {
+                package: self.package.clone(),
+                function,
+                capability,
+            },
+            None => GEntityScriptError::Runtime {
+                package: self.package.clone(),
+                message: error.to_string(),
+            },
+        }
+    }
+}
+
+/// Finds out whether `error` was caused by calling a function of a capability that was not declared.
+fn undeclared_capability(error: &EvalAltResult, capabilities: &ScriptCapabilities) -> Option<(String, ScriptCapability)> {
+    match error {
+        EvalAltResult::ErrorFunctionNotFound(signature, _) => {
+            let function = signature.split(|c: char| c == ' ' || c == '(').next().unwrap_or(signature);
+            let capability = ScriptCapability::of_function(function)?;
+            if capabilities.contains(capability) {
+                return None;
+            }
+            Some((function.to_string(), capability))
+        }
+        EvalAltResult::ErrorInFunctionCall(_, _, inner, _) => undeclared_capability(inner, capabilities),
+        _ => None,
+    }
+}
+
+fn push_command(commands: &ScriptCommandQueue, command: ScriptCommand) {
+    if let Ok(mut commands) = commands.lock() {
+        commands.push(command);
+    }
+}
+
+fn register_capability(engine: &mut Engine, capability: ScriptCapability, commands: &ScriptCommandQueue, shared_state: &ScriptSharedStateHandle) {
+    match capability {
+        ScriptCapability::Physics => {
+            let queue = commands.clone();
+            engine.register_fn("applyImpulse", move |x: f64, y: f64, z: f64| {
+                push_command(&queue, ScriptCommand::ApplyImpulse(DVec3::new(x, y, z)));
+            });
+            let queue = commands.clone();
+            engine.register_fn("setLinearVelocity", move |x: f64, y: f64, z: f64| {
+                push_command(&queue, ScriptCommand::SetLinearVelocity(DVec3::new(x, y, z)));
+            });
+            let queue = commands.clone();
+            engine.register_fn("setAngularVelocity", move |x: f64, y: f64, z: f64| {
+                push_command(&queue, ScriptCommand::SetAngularVelocity(DVec3::new(x, y, z)));
+            });
+            let queue = commands.clone();
+            engine.register_fn("undock", move || {
+                push_command(&queue, ScriptCommand::Undock(None));
+            });
+            let queue = commands.clone();
+            engine.register_fn("undock", move |port: ImmutableString| {
+                push_command(&queue, ScriptCommand::Undock(Some(port.to_string())));
+            });
+            let queue = commands.clone();
+            engine.register_fn("setJointTarget", move |joint: ImmutableString, target: f64| {
+                push_command(&queue, ScriptCommand::SetJointTarget {
+                    joint: joint.to_string(),
+                    target,
+                });
+            });
+        }
+        ScriptCapability::Spawn => {
+            let queue = commands.clone();
+            engine.register_fn("spawn", move |path: ImmutableString, x: f64, y: f64, z: f64| {
+                push_command(&queue, ScriptCommand::Spawn {
+                    path: path.to_string(),
+                    offset: Vec3::new(x as f32, y as f32, z as f32),
+                });
+            });
+        }
+        ScriptCapability::Messaging => {
+            let queue = commands.clone();
+            engine.register_fn("sendMessage", move |name: ImmutableString, payload: Dynamic| {
+                push_command(&queue, ScriptCommand::SendMessage {
+                    name: name.to_string(),
+                    payload,
+                });
+            });
+        }
+        ScriptCapability::Ui => {
+            let queue = commands.clone();
+            engine.register_fn("showNotification", move |key: ImmutableString| {
+                push_command(&queue, ScriptCommand::ShowNotification(key.to_string()));
+            });
+        }
+        ScriptCapability::Animation => {
+            let queue = commands.clone();
+            engine.register_fn("playAnimation", move |name: ImmutableString| {
+                push_command(&queue, ScriptCommand::PlayAnimation(name.to_string()));
+            });
+            let queue = commands.clone();
+            engine.register_fn("stopAnimation", move || {
+                push_command(&queue, ScriptCommand::StopAnimation);
+            });
+        }
+        ScriptCapability::Atmosphere => {
+            let state = shared_state.clone();
+            engine.register_fn("getPressure", move |volume: ImmutableString| -> Result<f64, Box<EvalAltResult>> {
+                let pressure = state.lock().ok().and_then(|state| state.pressures.get(volume.as_str()).copied());
+                pressure.ok_or_else(|| format!("Unknown atmosphere volume '{}'", volume).into())
+            });
+            let queue = commands.clone();
+            engine.register_fn("setPressure", move |volume: ImmutableString, pressure: f64| {
+                push_command(&queue, ScriptCommand::SetPressure {
+                    volume: volume.to_string(),
+                    pressure,
+                });
+            });
+            let queue = commands.clone();
+            engine.register_fn("openDoor", move |door: ImmutableString| {
+                push_command(&queue, ScriptCommand::SetDoor {
+                    door: door.to_string(),
+                    open: true,
+                });
+            });
+            let queue = commands.clone();
+            engine.register_fn("closeDoor", move |door: ImmutableString| {
+                push_command(&queue, ScriptCommand::SetDoor {
+                    door: door.to_string(),
+                    open: false,
+                });
+            });
+        }
+    }
+}
+
+pub fn initialize_gentity_scripts(
+    uninitialized: Query<(Entity, &Handle<TomlAsset>, Option<&GEntityState>), (Without<GEntityScripts>, Without<GEntityInitializeFromTomlComponent>)>,
+    toml_assets: Res<Assets<TomlAsset>>,
+    script_assets: Res<Assets<RhaiScript>>,
+    mut cmds: Commands,
+) {
+    for (entity, toml_asset_handle, state) in uninitialized.iter() {
+        if matches!(state, Some(GEntityState::Failed(_))) {
+            continue;
+        }
+        let Some(toml_asset) = toml_assets.get(toml_asset_handle) else {
+            continue;
+        };
+        if toml_asset.script_assets.iter().any(|handle| script_assets.get(handle).is_none()) {
+            continue;
+        }
+        if !toml_asset.script_assets.is_empty() {
+            info!("Enabling scripts of package '{}' with capabilities: {}", toml_asset.identifier, toml_asset.capabilities);
+        }
+        let mut scripts = GEntityScripts::new(toml_asset.identifier.clone(), toml_asset.capabilities.clone());
+        for handle in toml_asset.script_assets.iter() {
+            let Some(script) = script_assets.get(handle) else {
+                continue;
+            };
+            if let Err(error) = scripts.load(script) {
+                error!("{}", error);
+            }
+        }
+        cmds.entity(entity).insert(scripts);
+    }
+}
+
+pub fn run_gentity_script_calls(
+    mut calls: EventReader<GEntityScriptCall>,
+    mut scripts: Query<&mut GEntityScripts>,
+) {
+    for call in calls.read() {
+        let Ok(mut scripts) = scripts.get_mut(call.entity) else {
+            continue;
+        };
+        if let Err(error) = scripts.call(&call.function, call.args.clone()) {
+            error!("{}", error);
+        }
+    }
+}
+
+pub fn apply_gentity_script_commands(
+    scripts: Query<(Entity, &GEntityScripts)>,
+    mut bodies: Query<(&mut LinearVelocity, &mut AngularVelocity, &InverseMass)>,
+    placements: Query<(&Transform, &GridCell<i64>)>,
+    gentities: Query<&Handle<TomlAsset>>,
+    children: Query<&Children>,
+    mut animation_players: Query<&mut AnimationPlayer>,
+    toml_assets: Res<Assets<TomlAsset>>,
+    gltf_assets: Res<Assets<Gltf>>,
+    asset_server: Res<AssetServer>,
+    mut calls: EventWriter<GEntityScriptCall>,
+    mut notifications: EventWriter<GEntityNotification>,
+    mut undock_requests: EventWriter<GEntityUndock>,
+    mut atmosphere_requests: EventWriter<GEntityAtmosphereRequest>,
+    mut joint_targets: EventWriter<GEntityJointTarget>,
+    localization: Res<Localization>,
+    mut cmds: Commands,
+) {
+    for (entity, entity_scripts) in scripts.iter() {
+        for command in entity_scripts.take_commands() {
+            match command {
+                ScriptCommand::ApplyImpulse(impulse) => {
+                    if let Ok((mut linear_velocity, _, inverse_mass)) = bodies.get_mut(entity) {
+                        linear_velocity.0 += impulse * inverse_mass.0;
+                    }
+                }
+                ScriptCommand::SetLinearVelocity(velocity) => {
+                    if let Ok((mut linear_velocity, _, _)) = bodies.get_mut(entity) {
+                        linear_velocity.0 = velocity;
+                    }
+                }
+                ScriptCommand::SetAngularVelocity(velocity) => {
+                    if let Ok((_, mut angular_velocity, _)) = bodies.get_mut(entity) {
+                        angular_velocity.0 = velocity;
+                    }
+                }
+                ScriptCommand::Undock(port) => {
+                    undock_requests.send(GEntityUndock {
+                        gentity: entity,
+                        port,
+                    });
+                }
+                ScriptCommand::SetJointTarget { joint, target } => {
+                    joint_targets.send(GEntityJointTarget {
+                        gentity: entity,
+                        joint,
+                        target,
+                    });
+                }
+                ScriptCommand::Spawn { path, offset } => {
+                    let Ok((transform, grid_cell)) = placements.get(entity) else {
+                        continue;
+                    };
+                    cmds.spawn(GEntityBundle {
+                        toml: asset_server.load(path),
+                        transform: Transform::from_translation(transform.translation + transform.rotation * offset)
+                            .with_rotation(transform.rotation),
+                        grid_cell: *grid_cell,
+                        ..default()
+                    });
+                }
+                ScriptCommand::SendMessage { name, payload } => {
+                    for (other, _) in scripts.iter().filter(|(other, _)| *other != entity) {
+                        calls.send(GEntityScriptCall::new(other, "onMessage", vec![
+                            entity_scripts.package().to_string().into(),
+                            name.clone().into(),
+                            payload.clone(),
+                        ]));
+                    }
+                }
+                ScriptCommand::ShowNotification(key) => {
+                    notifications.send(GEntityNotification {
+                        entity,
+                        text: localization.get(&key),
+                    });
+                }
+                ScriptCommand::PlayAnimation(name) => {
+                    let clip = gentities.get(entity).ok()
+                        .and_then(|handle| toml_assets.get(handle))
+                        .and_then(|toml_asset| gltf_assets.get(&toml_asset.gltf_file_asset))
+                        .and_then(|gltf| gltf.named_animations.get(&name));
+                    let Some(clip) = clip else {
+                        warn!("Animation '{}' not found for entity: {:?}", name, entity);
+                        continue;
+                    };
+                    for descendant in children.iter_descendants(entity) {
+                        if let Ok(mut animation_player) = animation_players.get_mut(descendant) {
+                            animation_player.play(clip.clone());
+                        }
+                    }
+                }
+                ScriptCommand::SetPressure { volume, pressure } => {
+                    atmosphere_requests.send(GEntityAtmosphereRequest {
+                        gentity: entity,
+                        request: AtmosphereRequest::SetPressure { volume, pressure },
+                    });
+                }
+                ScriptCommand::SetDoor { door, open } => {
+                    atmosphere_requests.send(GEntityAtmosphereRequest {
+                        gentity: entity,
+                        request: AtmosphereRequest::SetDoor { door, open },
+                    });
+                }
+                ScriptCommand::StopAnimation => {
+                    for descendant in children.iter_descendants(entity) {
+                        if let Ok(mut animation_player) = animation_players.get_mut(descendant) {
+                            animation_player.pause();
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn display_gentity_notifications(
+    mut notifications: EventReader<GEntityNotification>,
+    time: Res<Time>,
+    mut timers: Query<(Entity, &mut GEntityNotificationTimer)>,
+    mut cmds: Commands,
+) {
+    for (entity, mut timer) in timers.iter_mut() {
+        timer.0.tick(time.delta());
+        if timer.0.finished() {
+            cmds.entity(entity).despawn_recursive();
+        }
+    }
+    for notification in notifications.read() {
+        cmds.spawn((
+            TextBundle::from_section(
+                notification.text.clone(),
+                TextStyle {
+                    font_size: 24.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    ..default()
+                }),
+            GEntityNotificationTimer(Timer::from_seconds(NOTIFICATION_SECONDS, TimerMode::Once)),
+        ));
+    }
+}
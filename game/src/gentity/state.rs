@@ -0,0 +1,107 @@
+use bevy::asset::{LoadState, RecursiveDependencyLoadState};
+use bevy::prelude::*;
+use crate::gentity::asset_loaders::toml_asset_loader::TomlAsset;
+use crate::gentity::gltf::hook::ProcessGEntity;
+use crate::gentity::gltf::pp_collision::GEntityMassPending;
+use crate::gentity::plugin::GEntityInitializeFromTomlComponent;
+
+/// Where a GEntity is in its lifecycle.
+#[derive(Component, Default, Debug, Clone, PartialEq)]
+pub enum GEntityState {
+    /// Waiting for the [TomlAsset] and its dependencies.
+    #[default]
+    Loading,
+    /// Waiting for the gltf scene instance to be spawned.
+    Instantiating,
+    /// The gltf hooks ran, waiting for their results to be applied.
+    ProcessingHooks,
+    Ready,
+    Failed(String),
+}
+
+#[derive(Event)]
+pub struct GEntityReady {
+    pub entity: Entity,
+}
+
+#[derive(Event)]
+pub struct GEntityFailed {
+    pub entity: Entity,
+    pub reason: String,
+}
+
+/// The child shown in place of a GEntity whose assets failed to load.
+#[derive(Component)]
+pub struct GEntityPlaceholder;
+
+#[derive(Resource)]
+pub struct GEntityPlaceholderAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+pub fn setup_gentity_placeholder_assets(
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut cmds: Commands,
+) {
+    cmds.insert_resource(GEntityPlaceholderAssets {
+        mesh: meshes.add(shape::Cube { size: 1.0 }.into()),
+        material: materials.add(StandardMaterial {
+            base_color: Color::FUCHSIA,
+            emissive: Color::FUCHSIA,
+            unlit: true,
+            ..default()
+        }),
+    });
+}
+
+pub fn detect_failed_gentity_loads(
+    gentities: Query<(Entity, &Handle<TomlAsset>, &GEntityState)>,
+    asset_server: Res<AssetServer>,
+    placeholder_assets: Res<GEntityPlaceholderAssets>,
+    mut failed_events: EventWriter<GEntityFailed>,
+    mut cmds: Commands,
+) {
+    for (entity, toml_asset_handle, state) in gentities.iter() {
+        if !matches!(state, GEntityState::Loading | GEntityState::Instantiating) {
+            continue;
+        }
+        let reason = match asset_server.get_load_states(toml_asset_handle) {
+            Some((LoadState::Failed, _, _)) => format!("Failed loading {:?}", toml_asset_handle.path()),
+            Some((_, _, RecursiveDependencyLoadState::Failed)) => format!("Failed loading the dependencies of {:?}", toml_asset_handle.path()),
+            _ => continue,
+        };
+        error!("GEntity {:?} failed: {}", entity, reason);
+        cmds.entity(entity)
+            .remove::<GEntityInitializeFromTomlComponent>()
+            .remove::<ProcessGEntity>()
+            .insert(GEntityState::Failed(reason.clone()))
+            .insert((GlobalTransform::default(), VisibilityBundle::default()))
+            .with_children(|parent| {
+                parent.spawn((
+                    PbrBundle {
+                        mesh: placeholder_assets.mesh.clone(),
+                        material: placeholder_assets.material.clone(),
+                        ..default()
+                    },
+                    GEntityPlaceholder,
+                ));
+            });
+        failed_events.send(GEntityFailed { entity, reason });
+    }
+}
+
+pub fn finish_gentity_processing(
+    gentities: Query<(Entity, &GEntityState), (Without<ProcessGEntity>, Without<GEntityMassPending>)>,
+    mut ready_events: EventWriter<GEntityReady>,
+    mut cmds: Commands,
+) {
+    for (entity, state) in gentities.iter() {
+        if *state != GEntityState::ProcessingHooks {
+            continue;
+        }
+        cmds.entity(entity).insert(GEntityState::Ready);
+        ready_events.send(GEntityReady { entity });
+    }
+}
@@ -0,0 +1,223 @@
+use std::sync::Arc;
+use bevy::ecs::system::EntityCommands;
+use bevy::gltf::GltfExtras;
+use bevy::prelude::*;
+use bevy::render::primitives::Aabb;
+use bevy::utils::HashMap;
+use bevy_xpbd_3d::prelude::*;
+use crate::gentity::asset_loaders::toml_asset_loader::TomlAsset;
+use crate::gentity::state::GEntityState;
+
+/// Maps the entities of the instance a template was captured from to the entities of a new instance.
+pub type GEntityTemplateEntityMap = HashMap<Entity, Entity>;
+
+/// A component value captured into a [GEntityTemplate].
+pub trait GEntityTemplateComponent: Send + Sync + 'static {
+    fn insert(&self, entity_cmds: &mut EntityCommands, entity_map: &GEntityTemplateEntityMap);
+}
+
+struct CapturedComponent<T: Component> {
+    value: T,
+    clone: fn(&T, &GEntityTemplateEntityMap) -> T,
+}
+
+impl<T: Component> GEntityTemplateComponent for CapturedComponent<T> {
+    fn insert(&self, entity_cmds: &mut EntityCommands, entity_map: &GEntityTemplateEntityMap) {
+        entity_cmds.insert((self.clone)(&self.value, entity_map));
+    }
+}
+
+type ComponentCapture = Box<dyn Fn(EntityRef) -> Option<Box<dyn GEntityTemplateComponent>> + Send + Sync>;
+
+/// The component types copied into templates. Components of other types are not part of a template,
+/// so hooks adding their own components have to register them here.
+#[derive(Resource)]
+pub struct GEntityTemplateRegistry {
+    captures: Vec<ComponentCapture>,
+}
+
+impl Default for GEntityTemplateRegistry {
+    fn default() -> Self {
+        let mut registry = Self { captures: vec![] };
+        registry.register::<Name>();
+        registry.register::<Transform>();
+        registry.register::<GlobalTransform>();
+        registry.register::<Visibility>();
+        registry.register::<InheritedVisibility>();
+        registry.register::<ViewVisibility>();
+        registry.register::<Aabb>();
+        registry.register::<Handle<Mesh>>();
+        registry.register::<Handle<StandardMaterial>>();
+        registry.register::<GltfExtras>();
+        registry.register_with::<AnimationPlayer>(|_, _| AnimationPlayer::default());
+        registry.register::<Collider>();
+        registry.register::<Sensor>();
+        registry.register::<ColliderDensity>();
+        registry.register::<CollisionLayers>();
+        registry
+    }
+}
+
+impl GEntityTemplateRegistry {
+    pub fn register<T: Component + Clone>(&mut self) {
+        self.register_with::<T>(|value, _| value.clone());
+    }
+
+    /// Registers a component type whose values are created by `clone`, eg. to remap the entities it refers to.
+    pub fn register_with<T: Component>(&mut self, clone: fn(&T, &GEntityTemplateEntityMap) -> T) {
+        self.captures.push(Box::new(move |entity_ref: EntityRef| {
+            let value = entity_ref.get::<T>()?;
+            let captured: Box<dyn GEntityTemplateComponent> = Box::new(CapturedComponent {
+                value: clone(value, &GEntityTemplateEntityMap::default()),
+                clone,
+            });
+            Some(captured)
+        }));
+    }
+
+    fn capture_node(&self, entity_ref: EntityRef) -> Vec<Box<dyn GEntityTemplateComponent>> {
+        self.captures.iter().filter_map(|capture| capture(entity_ref)).collect()
+    }
+}
+
+struct GEntityTemplateNode {
+    source: Entity,
+    /// Index of the parent node, `None` for children of the GEntity root.
+    parent: Option<usize>,
+    components: Vec<Box<dyn GEntityTemplateComponent>>,
+}
+
+/// The processed entity hierarchy below a GEntity root, captured once the first instance of a
+/// package is [GEntityState::Ready].
+pub struct GEntityTemplate {
+    source: Entity,
+    nodes: Vec<GEntityTemplateNode>,
+}
+
+impl GEntityTemplate {
+    /// Captures the registered components of all descendants of `root`.
+    pub fn capture(world: &World, registry: &GEntityTemplateRegistry, root: Entity) -> Self {
+        let mut nodes = vec![];
+        let mut pending = vec![(root, None)];
+        while let Some((entity, parent)) = pending.pop() {
+            let Some(children) = world.get::<Children>(entity) else {
+                continue;
+            };
+            for child in children.iter().rev() {
+                let Some(child_ref) = world.get_entity(*child) else {
+                    continue;
+                };
+                nodes.push(GEntityTemplateNode {
+                    source: *child,
+                    parent,
+                    components: registry.capture_node(child_ref),
+                });
+                pending.push((*child, Some(nodes.len() - 1)));
+            }
+        }
+        Self { source: root, nodes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Spawns a copy of the captured hierarchy below `root`.
+    pub fn instantiate(&self, root: Entity, cmds: &mut Commands) {
+        let mut entity_map = GEntityTemplateEntityMap::with_capacity(self.nodes.len() + 1);
+        entity_map.insert(self.source, root);
+        let entities = self.nodes.iter()
+            .map(|node| {
+                let entity = cmds.spawn_empty().id();
+                entity_map.insert(node.source, entity);
+                entity
+            })
+            .collect::<Vec<_>>();
+        for (node, entity) in self.nodes.iter().zip(entities.iter()) {
+            let mut entity_cmds = cmds.entity(*entity);
+            for component in node.components.iter() {
+                component.insert(&mut entity_cmds, &entity_map);
+            }
+            let parent = node.parent.map(|parent| entities[parent]).unwrap_or(root);
+            entity_cmds.set_parent(parent);
+        }
+    }
+}
+
+enum GEntityTemplateSlot {
+    /// The template is captured from the given GEntity once it is ready.
+    Pending(Entity),
+    Ready(Arc<GEntityTemplate>),
+}
+
+/// The [GEntityTemplate]s of all packages spawned so far.
+#[derive(Default, Resource)]
+pub struct GEntityTemplates {
+    templates: HashMap<AssetId<TomlAsset>, GEntityTemplateSlot>,
+}
+
+/// How a new GEntity of a package gets its entity hierarchy.
+pub enum GEntityTemplateUse {
+    /// No template exists yet, the GEntity runs the full pipeline and becomes the template source.
+    Capture,
+    /// Another GEntity of the package is still being captured.
+    Wait,
+    Instantiate(Arc<GEntityTemplate>),
+}
+
+/// Marks the GEntity a template is captured from once it is ready.
+#[derive(Default, Component)]
+pub struct GEntityTemplateSource;
+
+impl GEntityTemplates {
+    /// Decides how `entity` gets its hierarchy. `source_alive` tells whether a pending template source
+    /// is still going to become ready, otherwise `entity` takes over.
+    pub fn reserve(&mut self, id: AssetId<TomlAsset>, entity: Entity, source_alive: impl Fn(Entity) -> bool) -> GEntityTemplateUse {
+        match self.templates.get(&id) {
+            Some(GEntityTemplateSlot::Ready(template)) => return GEntityTemplateUse::Instantiate(template.clone()),
+            Some(GEntityTemplateSlot::Pending(source)) if source_alive(*source) => return GEntityTemplateUse::Wait,
+            _ => {}
+        }
+        self.templates.insert(id, GEntityTemplateSlot::Pending(entity));
+        GEntityTemplateUse::Capture
+    }
+
+    pub fn insert(&mut self, id: AssetId<TomlAsset>, template: GEntityTemplate) {
+        self.templates.insert(id, GEntityTemplateSlot::Ready(Arc::new(template)));
+    }
+
+    /// Drops the template of a package, later spawns run the full pipeline again.
+    pub fn invalidate(&mut self, id: AssetId<TomlAsset>) {
+        self.templates.remove(&id);
+    }
+}
+
+pub fn capture_gentity_templates(world: &mut World) {
+    let mut sources = world.query_filtered::<(Entity, &GEntityState, &Handle<TomlAsset>), With<GEntityTemplateSource>>();
+    let ready = sources.iter(world)
+        .filter(|(_, state, _)| **state == GEntityState::Ready)
+        .map(|(entity, _, toml_asset_handle)| (entity, toml_asset_handle.id()))
+        .collect::<Vec<_>>();
+    for (entity, id) in ready {
+        let template = GEntityTemplate::capture(world, world.resource::<GEntityTemplateRegistry>(), entity);
+        debug!("Captured GEntity template of {:?} with {} nodes", entity, template.len());
+        world.resource_mut::<GEntityTemplates>().insert(id, template);
+        world.entity_mut(entity).remove::<GEntityTemplateSource>();
+    }
+}
+
+/// Drops templates whose package asset got modified, so the next spawn captures it again.
+pub fn invalidate_gentity_templates(
+    mut asset_events: EventReader<AssetEvent<TomlAsset>>,
+    mut templates: ResMut<GEntityTemplates>,
+) {
+    for event in asset_events.read() {
+        if let AssetEvent::Modified { id } | AssetEvent::Removed { id } = event {
+            templates.invalidate(*id);
+        }
+    }
+}
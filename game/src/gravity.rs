@@ -1,20 +1,15 @@
-use bevy::app::{App, Plugin, Startup};
-use bevy::asset::AssetServer;
-use bevy::hierarchy::BuildChildren;
-use bevy::input::{Axis, Input};
-use bevy::math::{DVec3, Vec3};
+use bevy::app::{App, Plugin};
 use bevy::prelude::*;
-use bevy::scene::SceneBundle;
-use bevy::transform::TransformBundle;
-use bevy::utils::default;
-use bevy_xpbd_3d::components::{Collider, RigidBody};
-use bevy_xpbd_3d::math::{Scalar, Vector2};
-use bevy_xpbd_3d::prelude::{LinearVelocity, Rotation};
-use big_space::camera::CameraController;
-use big_space::FloatingOrigin;
-use crate::bevy_stupid::vec3_to_dvec3;
-use crate::camera::Camera;
-use crate::player::{MovementAction, setup_player};
+use bevy::utils::HashMap;
+use bevy_xpbd_3d::math::{Quaternion, Vector};
+use bevy_xpbd_3d::prelude::*;
+use big_space::{FloatingOrigin, FloatingOriginSettings, GridCell};
+use crate::gentity::gltf::pp_gravity::GEntityGravityVolume;
+use crate::physics_math::double;
+use crate::player::{Player, PlayerUp};
+use crate::solarsystem::{Mass as CelestialMass, MassNoEffect, SimulationPosition};
+use crate::solarsystem::nbody::{self, NBody};
+use crate::solarsystem::time_warp::OnRails;
 
 
 pub struct GravityPlugin;
@@ -22,31 +17,127 @@
 impl Plugin for GravityPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_systems(Update, apply_gravity)
+            .add_systems(Update, (apply_gravity, apply_celestial_gravity))
         ;
     }
 }
 
-#[derive(Component, Default)]
-pub struct GravitySource {
-    pub strength: f32,
+/// The artificial gravity currently acting on a body, inserted while it is inside a [GEntityGravityVolume].
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct LocalGravity {
+    pub volume: Entity,
+    pub acceleration: Vector,
 }
 
-/// Applies [`ControllerGravity`] to character controllers.
+/// Accelerates dynamic bodies and players inside gravity volumes and turns the "up" of players
+/// against the gravity they are in, back to the default up once they leave the volumes.
 fn apply_gravity(
-    time: Res<Time>,
-    gravity_sources: Query<(&GravitySource, &Transform, &Children)>,
-    mut child: Query<&mut LinearVelocity>,
+    time: Res<Time<Physics>>,
+    volumes: Query<(Entity, &GEntityGravityVolume, &GlobalTransform)>,
+    mut bodies: Query<(Entity, &RigidBody, &GlobalTransform, &mut LinearVelocity, Option<&LocalGravity>, Option<&mut PlayerUp>, Option<&mut Rotation>, Has<Player>)>,
+    mut cmds: Commands,
 ) {
-    let delta_time = time.delta_seconds();
-
-    for (gravity_source, transform, children) in gravity_sources.iter() {
-        for &child_entity in children.iter() {
-            let mut velocity = child.get_mut(child_entity).unwrap();
-            let direction = transform.rotation.mul_vec3(Vec3::new(0.0, gravity_source.strength, 0.0));
+    let delta_time = time.delta_seconds_f64();
+    for (entity, rigid_body, body_transform, mut linear_velocity, local_gravity, player_up, rotation, is_player) in bodies.iter_mut() {
+        if !rigid_body.is_dynamic() && !is_player {
+            continue;
+        }
+        // A GEntity is not pulled by its own volumes.
+        let gravity = volumes.iter()
+            .filter(|(_, volume, _)| volume.gentity != entity)
+            .find_map(|(volume_entity, volume, transform)| {
+                volume.acceleration_at(transform, body_transform.translation()).map(|acceleration| LocalGravity {
+                    volume: volume_entity,
+                    acceleration,
+                })
+            });
+        let up = match gravity {
+            Some(gravity) => {
+                linear_velocity.0 += gravity.acceleration * delta_time;
+                if local_gravity != Some(&gravity) {
+                    cmds.entity(entity).insert(gravity);
+                }
+                -gravity.acceleration.normalize_or_zero()
+            }
+            None => {
+                if local_gravity.is_none() {
+                    continue;
+                }
+                cmds.entity(entity).remove::<LocalGravity>();
+                PlayerUp::default().0
+            }
+        };
+        if let (Some(mut player_up), Some(mut rotation)) = (player_up, rotation) {
+            if up != Vector::ZERO && up != player_up.0 {
+                rotation.0 = Quaternion::from_rotation_arc(player_up.0, up) * rotation.0;
+                player_up.0 = up;
+            }
+        }
+    }
+}
 
-            let gravity = direction * gravity_source.strength;
-            velocity.0 += vec3_to_dvec3(gravity * delta_time);
+/// Accelerates dynamic bodies and players with the gravity of all celestial bodies, evaluated at
+/// their big_space position.
+///
+/// Bodies inside a gravity volume fall together with the GEntity of the volume: they get the
+/// celestial gravity at its position, so only the artificial gravity moves them relative to it.
+/// Vessels [OnRails] follow their conic instead.
+#[allow(clippy::type_complexity)]
+fn apply_celestial_gravity(
+    time: Res<Time<Physics>>,
+    floating_origin_settings: Res<FloatingOriginSettings>,
+    origins: Query<&GridCell<i64>, With<FloatingOrigin>>,
+    attractors: Query<(&CelestialMass, &Transform, &GridCell<i64>, Option<&SimulationPosition>), Without<MassNoEffect>>,
+    volumes: Query<&GEntityGravityVolume>,
+    transforms: Query<&GlobalTransform>,
+    mut bodies: Query<(Entity, &RigidBody, &mut LinearVelocity, Option<&LocalGravity>, Has<Player>), Without<OnRails>>,
+) {
+    let Ok(origin_grid_cell) = origins.get_single() else {
+        return;
+    };
+    // Global transforms are relative to the grid cell of the floating origin.
+    let origin = floating_origin_settings.grid_position_double::<i64>(origin_grid_cell, &Transform::IDENTITY);
+    let mut states = attractors.iter()
+        .map(|(mass, transform, grid_cell, simulation_position)| NBody {
+            position: simulation_position.map_or_else(|| floating_origin_settings.grid_position_double::<i64>(grid_cell, transform), |position| position.0),
+            velocity: Vector::ZERO,
+            mu: double::gravitational_parameter(mass.value()),
+            moves: false,
+        })
+        .collect::<Vec<_>>();
+    if states.is_empty() {
+        return;
+    }
+    // The entity each body takes its gravity from, and the index of that entity's state.
+    let mut samples = vec![];
+    let mut sample_indices = HashMap::new();
+    for (entity, rigid_body, _, local_gravity, is_player) in bodies.iter() {
+        if !rigid_body.is_dynamic() && !is_player {
+            continue;
+        }
+        let sample = local_gravity
+            .and_then(|local_gravity| volumes.get(local_gravity.volume).ok())
+            .map_or(entity, |volume| volume.gentity);
+        if !sample_indices.contains_key(&sample) {
+            let Ok(transform) = transforms.get(sample) else {
+                continue;
+            };
+            sample_indices.insert(sample, states.len());
+            states.push(NBody {
+                position: origin + transform.translation().as_dvec3(),
+                velocity: Vector::ZERO,
+                mu: 0.0,
+                moves: true,
+            });
         }
+        samples.push((entity, sample_indices[&sample]));
     }
-}
\ No newline at end of file
+    let mut accelerations = vec![Vector::ZERO; states.len()];
+    nbody::direct_accelerations(&states, &mut accelerations);
+    let delta_time = time.delta_seconds_f64();
+    for (entity, index) in samples {
+        if let Ok((_, _, mut linear_velocity, ..)) = bodies.get_mut(entity) {
+            linear_velocity.0 += accelerations[index] * delta_time;
+        }
+    }
+}
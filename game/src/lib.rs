@@ -0,0 +1,13 @@
+pub mod solarsystem;
+pub mod physics_math;
+pub mod common_math;
+pub mod bevy_stupid;
+pub mod orbit;
+pub mod spaceship;
+pub mod camera;
+pub mod player;
+pub mod gravity;
+pub mod gentity;
+pub mod localization;
+pub mod fixed_joint_sample;
+pub mod atmosphere;
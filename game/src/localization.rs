@@ -221,4 +221,21 @@ pub fn get_in(&self, culture: &String, key: &String) -> Option<String> {
 
         None
     }
+
+    /**
+     * #### Description
+     * Removes a localization.
+     *
+     * #### Parameters
+     * * `culture` - The culture code of the localization.
+     * * `key` - The key of the localization.
+     */
+    pub fn remove(&mut self, culture: &String, key: &String) {
+        for (l, pairs) in &mut self.languages {
+            if l == culture {
+                pairs.retain(|pair| pair.key != *key);
+            }
+        }
+        self.languages.retain(|(_, pairs)| !pairs.is_empty());
+    }
 }
\ No newline at end of file
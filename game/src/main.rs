@@ -1,16 +1,3 @@
-mod solarsystem;
-mod physics_math;
-mod common_math;
-mod bevy_stupid;
-mod orbit;
-mod spaceship;
-mod camera;
-mod player;
-mod gravity;
-mod gentity;
-mod localization;
-mod fixed_joint_sample;
-
 use std::io;
 
 use bevy::prelude::*;
@@ -25,6 +12,7 @@
     camera::{CameraController, CameraInput},
     FloatingOrigin, GridCell,
 };
+use untitled::{atmosphere, gentity, gravity, localization, player, solarsystem, spaceship};
 
 fn main() {
     // simple_logging::log_to_file("log.txt", log::LevelFilter::Info);
@@ -54,6 +42,7 @@ fn main() {
             big_space::bevy_xpbd::floating_origin_sync::FloatingOriginSyncPlugin::<i64>::new(PostUpdate),
             bevy_xpbd_3d::plugins::PhysicsDebugPlugin::default(),
             ))
+        // Celestial gravity is applied per body by gravity::GravityPlugin.
         .insert_resource(Gravity(DVec3::ZERO))
         .add_plugins((
             gentity::plugin::GEntityPlugin,
@@ -61,6 +50,8 @@ fn main() {
             solarsystem::PlanetsPlugin,
             // camera::CameraPlugin,
             player::PlayerPlugin,
+            gravity::GravityPlugin,
+            atmosphere::AtmospherePlugin,
             spaceship::SpaceshipPlugin,
         ))
         .insert_resource(ClearColor(Color::BLACK))
@@ -1,11 +1,370 @@
-use bevy::prelude::{Component, Entity, Query, Transform, Without};
+use bevy::math::DVec3;
+use bevy::prelude::{warn, Component, Entity};
+use crate::physics_math::double;
+
+/**
+ * Below this, eccentricities count as circular and angular momenta as zero.
+ */
+const ORBIT_EPSILON: f64 = 1e-10;
+
+/**
+ * #### Description
+ * Converts a vector from the orbital reference frame (+Z north) to the game frame (+Y north).
+ */
+pub fn to_world_frame(v: DVec3) -> DVec3 {
+    DVec3::new(v.x, v.z, -v.y)
+}
+
+/**
+ * #### Description
+ * Converts a vector from the game frame (+Y north) to the orbital reference frame (+Z north).
+ */
+pub fn from_world_frame(v: DVec3) -> DVec3 {
+    DVec3::new(v.x, -v.z, v.y)
+}
 
 /**
  * #### Description
- * A component that describes the orbit of an entity.
+ * A component that describes the orbit of an entity around its parent body as classical
+ * (Keplerian) orbital elements.
+ *
+ * #### Remarks
+ * Entities with an orbit are moved "on rails": their position is computed from the elements at
+ * the current simulation time instead of being integrated.
+ * The reference plane is the XZ plane with +Y pointing north, the reference direction is +X.
+ *
+ * #### Fields
+ * * `parent` - The body orbited.
+ * * `mu` - The gravitational parameter of the parent and the body in meters cubed per second squared.
+ * * `semi_major_axis` - In meters, negative for hyperbolic orbits.
+ * * `eccentricity` - 0 for circular, below 1 for elliptic and above 1 for hyperbolic orbits.
+ * * `inclination`, `longitude_of_ascending_node`, `argument_of_periapsis` - In radians.
+ * * `mean_anomaly_at_epoch` - The mean anomaly in radians at `epoch`.
+ * * `epoch` - The simulation time in seconds the mean anomaly refers to.
  */
-#[derive(Debug, Component, Default, Clone, Copy, PartialEq)]
-pub struct  Orbit {
+#[derive(Debug, Component, Clone, Copy, PartialEq)]
+pub struct Orbit {
+    pub parent: Entity,
+    pub mu: f64,
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    pub inclination: f64,
+    pub longitude_of_ascending_node: f64,
+    pub argument_of_periapsis: f64,
+    pub mean_anomaly_at_epoch: f64,
+    pub epoch: f64,
+}
+
+impl Orbit {
+    /**
+     * #### Description
+     * Computes the orbit from state vectors.
+     *
+     * #### Parameters
+     * * `parent` - The body orbited.
+     * * `mu` - The gravitational parameter of the parent and the body.
+     * * `position` - The position relative to the parent in meters.
+     * * `velocity` - The velocity relative to the parent in meters per second.
+     * * `time` - The simulation time of the state.
+     *
+     * #### Returns
+     * The orbit, or `None` for radial and parabolic trajectories which have no classical elements.
+     */
+    pub fn from_state(parent: Entity, mu: f64, position: DVec3, velocity: DVec3, time: f64) -> Option<Self> {
+        let position = from_world_frame(position);
+        let velocity = from_world_frame(velocity);
+        let distance = position.length();
+        let angular_momentum = position.cross(velocity);
+        let node = DVec3::Z.cross(angular_momentum);
+        let eccentricity_vector = ((velocity.length_squared() - mu / distance) * position - position.dot(velocity) * velocity) / mu;
+        let eccentricity = eccentricity_vector.length();
+        let energy = velocity.length_squared() / 2.0 - mu / distance;
+        if angular_momentum.length() <= ORBIT_EPSILON * distance * velocity.length() || (eccentricity - 1.0).abs() <= ORBIT_EPSILON {
+            return None;
+        }
+        let normal = angular_momentum.normalize();
+        let (node_axis, longitude_of_ascending_node) = if node.length() > ORBIT_EPSILON {
+            (node.normalize(), node.y.atan2(node.x))
+        } else {
+            (DVec3::X, 0.0)
+        };
+        // Angles within the orbital plane are measured from the ascending node.
+        let in_plane_axis = normal.cross(node_axis);
+        let angle_in_plane = |v: DVec3| v.dot(in_plane_axis).atan2(v.dot(node_axis));
+        let argument_of_periapsis = if eccentricity > ORBIT_EPSILON { angle_in_plane(eccentricity_vector) } else { 0.0 };
+        let true_anomaly = angle_in_plane(position) - argument_of_periapsis;
+        Some(Self {
+            parent,
+            mu,
+            semi_major_axis: -mu / (2.0 * energy),
+            eccentricity,
+            inclination: normal.z.clamp(-1.0, 1.0).acos(),
+            longitude_of_ascending_node,
+            argument_of_periapsis,
+            mean_anomaly_at_epoch: double::mean_anomaly_from_true(true_anomaly, eccentricity),
+            epoch: time,
+        })
+    }
+
+    /**
+     * #### Description
+     * Gets the mean anomaly in radians at a simulation time.
+     */
+    pub fn mean_anomaly_at(&self, time: f64) -> f64 {
+        let mean_anomaly = self.mean_anomaly_at_epoch + double::mean_motion(self.mu, self.semi_major_axis) * (time - self.epoch);
+        if self.is_closed() { mean_anomaly.rem_euclid(std::f64::consts::TAU) } else { mean_anomaly }
+    }
+
+    /**
+     * #### Description
+     * Gets the true anomaly in radians at a simulation time.
+     *
+     * #### Remarks
+     * Logs a warning and falls back to the last estimate if Kepler's equation does not converge.
+     */
+    pub fn true_anomaly_at(&self, time: f64) -> f64 {
+        double::true_anomaly_from_mean(self.mean_anomaly_at(time), self.eccentricity).unwrap_or_else(|error| {
+            warn!("{}", error);
+            error.anomaly
+        })
+    }
+
+    /**
+     * #### Description
+     * Computes the state vectors at a true anomaly.
+     *
+     * #### Returns
+     * The position in meters and the velocity in meters per second, relative to the parent.
+     */
+    pub fn state_at_true_anomaly(&self, true_anomaly: f64) -> (DVec3, DVec3) {
+        let (position, velocity) = double::state_from_elements(
+            self.mu,
+            self.semi_major_axis,
+            self.eccentricity,
+            self.inclination,
+            self.longitude_of_ascending_node,
+            self.argument_of_periapsis,
+            true_anomaly,
+        );
+        (to_world_frame(position), to_world_frame(velocity))
+    }
+
+    /**
+     * #### Description
+     * Propagates the orbit to a simulation time.
+     *
+     * #### Returns
+     * The position in meters and the velocity in meters per second, relative to the parent.
+     */
+    pub fn state_at(&self, time: f64) -> (DVec3, DVec3) {
+        self.state_at_true_anomaly(self.true_anomaly_at(time))
+    }
+
+    /**
+     * Whether the orbit is an ellipse (or circle) rather than a hyperbola.
+     */
+    pub fn is_closed(&self) -> bool {
+        self.eccentricity < 1.0
+    }
+
+    /**
+     * Gets the orbital period in seconds, `None` for hyperbolic orbits.
+     */
+    pub fn period(&self) -> Option<f64> {
+        self.is_closed().then(|| double::orbital_period(self.mu, self.semi_major_axis))
+    }
+
+    /**
+     * Gets the distance of the periapsis to the parent in meters.
+     */
+    pub fn periapsis(&self) -> f64 {
+        self.semi_major_axis * (1.0 - self.eccentricity)
+    }
+
+    /**
+     * Gets the distance of the apoapsis to the parent in meters, `None` for hyperbolic orbits.
+     */
+    pub fn apoapsis(&self) -> Option<f64> {
+        self.is_closed().then(|| self.semi_major_axis * (1.0 + self.eccentricity))
+    }
+
+    /**
+     * #### Description
+     * Converts a maneuver given in the orbital frame at a simulation time into a velocity change.
+     *
+     * #### Parameters
+     * * `time` - The simulation time of the maneuver.
+     * * `prograde` - Along the velocity in meters per second.
+     * * `normal` - Along the angular momentum, perpendicular to the orbital plane, in meters per second.
+     * * `radial` - Perpendicular to both, pointing away from the parent, in meters per second.
+     *
+     * #### Returns
+     * The velocity change in meters per second in the game frame.
+     */
+    pub fn maneuver_delta_v(&self, time: f64, prograde: f64, normal: f64, radial: f64) -> DVec3 {
+        let (position, velocity) = self.state_at(time);
+        let prograde_axis = velocity.normalize();
+        let normal_axis = position.cross(velocity).normalize();
+        let radial_axis = prograde_axis.cross(normal_axis);
+        prograde_axis * prograde + normal_axis * normal + radial_axis * radial
+    }
+
+    /**
+     * #### Description
+     * Computes the orbit after an instantaneous velocity change.
+     * See <https://orbital-mechanics.space/orbital-maneuvers/impulsive-maneuvers.html>.
+     *
+     * #### Parameters
+     * * `time` - The simulation time of the maneuver.
+     * * `delta_v` - The velocity change in meters per second in the game frame.
+     *
+     * #### Returns
+     * The new orbit with its epoch at `time`, `None` if it has no classical elements.
+     */
+    pub fn after_impulse(&self, time: f64, delta_v: DVec3) -> Option<Self> {
+        let (position, velocity) = self.state_at(time);
+        Self::from_state(self.parent, self.mu, position, velocity + delta_v, time)
+    }
+}
+
+/**
+ * #### Description
+ * A transfer between two orbits around the same parent, as computed by [Transfer::hohmann] and
+ * [Transfer::bi_elliptic].
+ *
+ * #### Remarks
+ * Both orbits are treated as circular and coplanar, with their semi-major axes as radii.
+ *
+ * #### Fields
+ * * `burns` - The delta-v of every burn in meters per second, in order.
+ * * `duration` - The time from the first to the last burn in seconds.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub burns: Vec<f64>,
+    pub duration: f64,
+}
+
+impl Transfer {
+    /**
+     * #### Description
+     * Computes a Hohmann transfer from one orbit to another, eg. between the orbits of two planets.
+     *
+     * #### Returns
+     * The transfer, or `None` if the orbits are around different parents or not closed.
+     */
+    pub fn hohmann(from: &Orbit, to: &Orbit) -> Option<Self> {
+        if from.parent != to.parent || !from.is_closed() || !to.is_closed() {
+            return None;
+        }
+        let (burns, duration) = double::hohmann_transfer(from.mu, from.semi_major_axis, to.semi_major_axis);
+        Some(Self { burns: burns.to_vec(), duration })
+    }
+
+    /**
+     * #### Description
+     * Computes a bi-elliptic transfer from one orbit to another via an intermediate apoapsis.
+     *
+     * #### Parameters
+     * * `apoapsis` - The radius of the intermediate apoapsis in meters, at least the larger of both radii.
+     *
+     * #### Returns
+     * The transfer, or `None` if the orbits are around different parents or not closed.
+     */
+    pub fn bi_elliptic(from: &Orbit, to: &Orbit, apoapsis: f64) -> Option<Self> {
+        if from.parent != to.parent || !from.is_closed() || !to.is_closed() || apoapsis < from.semi_major_axis.max(to.semi_major_axis) {
+            return None;
+        }
+        let (burns, duration) = double::bi_elliptic_transfer(from.mu, from.semi_major_axis, to.semi_major_axis, apoapsis);
+        Some(Self { burns: burns.to_vec(), duration })
+    }
+
+    /**
+     * Gets the summed delta-v of all burns in meters per second.
+     */
+    pub fn delta_v(&self) -> f64 {
+        self.burns.iter().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{PI, TAU};
+    use super::*;
+
+    const EARTH_MU: f64 = 3.986004418e14;
+
+    fn orbit(semi_major_axis: f64, eccentricity: f64, inclination: f64) -> Orbit {
+        Orbit {
+            parent: Entity::PLACEHOLDER,
+            mu: EARTH_MU,
+            semi_major_axis,
+            eccentricity,
+            inclination,
+            longitude_of_ascending_node: 1.0,
+            argument_of_periapsis: 2.0,
+            mean_anomaly_at_epoch: 0.7,
+            epoch: 100.0,
+        }
+    }
+
+    fn assert_angle_eq(a: f64, b: f64, what: &str) {
+        let difference = (a - b + PI).rem_euclid(TAU) - PI;
+        assert!(difference.abs() < 1e-7, "{}: {} != {}", what, a, b);
+    }
+
+    fn assert_state_round_trips(orbit: &Orbit) -> Orbit {
+        let (position, velocity) = orbit.state_at(orbit.epoch);
+        let result = Orbit::from_state(orbit.parent, orbit.mu, position, velocity, orbit.epoch).unwrap();
+        let (result_position, result_velocity) = result.state_at(orbit.epoch);
+        assert!(result_position.distance(position) < 1e-6 * position.length(), "{:?}: {} != {}", orbit, result_position, position);
+        assert!(result_velocity.distance(velocity) < 1e-6 * velocity.length(), "{:?}: {} != {}", orbit, result_velocity, velocity);
+        result
+    }
+
+    #[test]
+    fn elements_to_state_to_elements_round_trips() {
+        for (semi_major_axis, eccentricity) in [(7.0e6, 0.5), (7.0e8, 0.99), (-7.0e6, 1.5), (-2.0e6, 5.0)] {
+            let orbit = orbit(semi_major_axis, eccentricity, 0.3);
+            let result = assert_state_round_trips(&orbit);
+            assert!((result.semi_major_axis - semi_major_axis).abs() < 1e-6 * semi_major_axis.abs(), "{:?}: a = {}", orbit, result.semi_major_axis);
+            assert!((result.eccentricity - eccentricity).abs() < 1e-9, "{:?}: e = {}", orbit, result.eccentricity);
+            assert_angle_eq(result.inclination, orbit.inclination, "inclination");
+            assert_angle_eq(result.longitude_of_ascending_node, orbit.longitude_of_ascending_node, "longitude of ascending node");
+            assert_angle_eq(result.argument_of_periapsis, orbit.argument_of_periapsis, "argument of periapsis");
+            assert_angle_eq(result.mean_anomaly_at_epoch, orbit.mean_anomaly_at_epoch, "mean anomaly");
+        }
+    }
+
+    #[test]
+    fn circular_and_equatorial_states_round_trip() {
+        // Their node or periapsis is undefined, so only the states have to match.
+        assert_state_round_trips(&orbit(7.0e6, 0.0, 0.3));
+        assert_state_round_trips(&orbit(7.0e6, 0.5, 0.0));
+        assert_state_round_trips(&orbit(-7.0e6, 1.5, 0.0));
+    }
+
+    #[test]
+    fn hohmann_transfer_from_leo_to_geo() {
+        let transfer = Transfer::hohmann(&orbit(6678e3, 0.0, 0.0), &orbit(42164e3, 0.0, 0.0)).unwrap();
+        assert_eq!(transfer.burns.len(), 2);
+        assert!((transfer.delta_v() - 3.9e3).abs() < 50.0, "delta-v = {}", transfer.delta_v());
+        assert!((transfer.duration - 5.3 * 3600.0).abs() < 0.1 * 3600.0, "duration = {}", transfer.duration);
+        let back = Transfer::hohmann(&orbit(42164e3, 0.0, 0.0), &orbit(6678e3, 0.0, 0.0)).unwrap();
+        assert!((back.delta_v() - transfer.delta_v()).abs() < 1e-9);
+    }
 
+    #[test]
+    fn bi_elliptic_transfer_beats_hohmann_above_ratio() {
+        let from = orbit(7e6, 0.0, 0.0);
+        for (ratio, cheaper) in [(11.0, false), (11.9, false), (12.0, true), (15.6, true), (20.0, true)] {
+            let to = orbit(7e6 * ratio, 0.0, 0.0);
+            let hohmann = Transfer::hohmann(&from, &to).unwrap();
+            let bi_elliptic = Transfer::bi_elliptic(&from, &to, 1000.0 * to.semi_major_axis).unwrap();
+            assert_eq!(bi_elliptic.delta_v() < hohmann.delta_v(), cheaper, "ratio {}: {} vs {}", ratio, bi_elliptic.delta_v(), hohmann.delta_v());
+            assert!(bi_elliptic.duration > hohmann.duration);
+        }
+        assert!(Transfer::bi_elliptic(&from, &orbit(7e7, 0.0, 0.0), 6e7).is_none());
+        assert!(Transfer::hohmann(&from, &orbit(-7e7, 1.5, 0.0)).is_none());
+    }
 }
-// ToDo: https://orbital-mechanics.space/orbital-maneuvers/impulsive-maneuvers.html
\ No newline at end of file
@@ -45,6 +45,37 @@ pub fn compute_gravitational_force(mass_a: f32, mass_b: f32, distance: f32) -> f
 }
 
 pub mod double {
+    use bevy::math::{DQuat, DVec3};
+    use thiserror::Error;
+
+    /**
+     * The tolerance in radians at which the Kepler equation solvers stop iterating.
+     */
+    const KEPLER_TOLERANCE: f64 = 1e-12;
+
+    /**
+     * The maximum number of iterations of the Kepler equation solvers.
+     */
+    const KEPLER_MAX_ITERATIONS: usize = 50;
+
+    /**
+     * #### Description
+     * The Kepler equation solvers did not reach [KEPLER_TOLERANCE] within [KEPLER_MAX_ITERATIONS].
+     *
+     * #### Fields
+     * * `mean_anomaly`, `eccentricity` - The arguments of the solver.
+     * * `anomaly` - The last estimate of the anomaly the solver computes, in radians.
+     * * `step` - The size of the last Newton step in radians.
+     */
+    #[derive(Debug, Clone, Copy, PartialEq, Error)]
+    #[error("Kepler's equation did not converge for mean anomaly {mean_anomaly} and eccentricity {eccentricity}, the last step was {step} rad")]
+    pub struct KeplerNotConverged {
+        pub mean_anomaly: f64,
+        pub eccentricity: f64,
+        pub anomaly: f64,
+        pub step: f64,
+    }
+
     /**
      * The gravitational constant in meters cubed per kilogram per second squared.
      * (m^3 * kg^-1 * s^-2)
@@ -82,4 +113,361 @@ pub fn compute_gravitational_force2(mass_a: f64, mass_b: f64, distance_squared:
     pub fn compute_gravitational_force(mass_a: f64, mass_b: f64, distance: f64) -> f64 {
         compute_gravitational_force2(mass_a, mass_b, distance * distance)
     }
-}
\ No newline at end of file
+
+    /**
+     * #### Description
+     * Computes the standard gravitational parameter (mu) of a body.
+     *
+     * #### Parameters
+     * - `mass` -- The mass of the body in kilograms.
+     *
+     * #### Returns
+     * The gravitational parameter in meters cubed per second squared.
+     */
+    pub fn gravitational_parameter(mass: f64) -> f64 {
+        GRAVITATIONAL_CONSTANT * mass
+    }
+
+    /**
+     * #### Description
+     * Computes the orbital speed at a distance using the vis-viva equation.
+     *
+     * #### Parameters
+     * - `mu` -- The gravitational parameter of the central body in meters cubed per second squared.
+     * - `distance` -- The distance to the central body in meters.
+     * - `semi_major_axis` -- The semi-major axis of the orbit in meters, negative for hyperbolic orbits.
+     *
+     * #### Returns
+     * The orbital speed in meters per second.
+     */
+    pub fn vis_viva_velocity(mu: f64, distance: f64, semi_major_axis: f64) -> f64 {
+        (mu * (2.0 / distance - 1.0 / semi_major_axis)).sqrt()
+    }
+
+    /**
+     * #### Description
+     * Computes the speed of a circular orbit.
+     *
+     * #### Parameters
+     * - `mu` -- The gravitational parameter of the central body in meters cubed per second squared.
+     * - `distance` -- The radius of the orbit in meters.
+     *
+     * #### Returns
+     * The orbital speed in meters per second.
+     */
+    pub fn circular_velocity(mu: f64, distance: f64) -> f64 {
+        (mu / distance).sqrt()
+    }
+
+    /**
+     * #### Description
+     * Computes the escape velocity at a distance.
+     *
+     * #### Parameters
+     * - `mu` -- The gravitational parameter of the central body in meters cubed per second squared.
+     * - `distance` -- The distance to the central body in meters.
+     *
+     * #### Returns
+     * The escape velocity in meters per second.
+     */
+    pub fn escape_velocity(mu: f64, distance: f64) -> f64 {
+        (2.0 * mu / distance).sqrt()
+    }
+
+    /**
+     * #### Description
+     * Computes the period of an elliptic orbit.
+     *
+     * #### Parameters
+     * - `mu` -- The gravitational parameter of the central body in meters cubed per second squared.
+     * - `semi_major_axis` -- The semi-major axis of the orbit in meters.
+     *
+     * #### Returns
+     * The orbital period in seconds.
+     */
+    pub fn orbital_period(mu: f64, semi_major_axis: f64) -> f64 {
+        std::f64::consts::TAU * (semi_major_axis.powi(3) / mu).sqrt()
+    }
+
+    /**
+     * #### Description
+     * Computes the mean motion, the average angular velocity, of an orbit.
+     *
+     * #### Parameters
+     * - `mu` -- The gravitational parameter of the central body in meters cubed per second squared.
+     * - `semi_major_axis` -- The semi-major axis of the orbit in meters, negative for hyperbolic orbits.
+     *
+     * #### Returns
+     * The mean motion in radians per second.
+     */
+    pub fn mean_motion(mu: f64, semi_major_axis: f64) -> f64 {
+        (mu / semi_major_axis.abs().powi(3)).sqrt()
+    }
+
+    /**
+     * #### Description
+     * Computes the radius of the sphere of influence of a body orbiting a much heavier one,
+     * within which the body dominates the motion of small objects.
+     *
+     * #### Parameters
+     * - `semi_major_axis` -- The semi-major axis of the orbit of the body in meters.
+     * - `mass` -- The mass of the body in kilograms.
+     * - `parent_mass` -- The mass of the body orbited in kilograms.
+     *
+     * #### Returns
+     * The radius of the sphere of influence in meters.
+     */
+    pub fn sphere_of_influence_radius(semi_major_axis: f64, mass: f64, parent_mass: f64) -> f64 {
+        semi_major_axis * (mass / parent_mass).powf(0.4)
+    }
+
+    /**
+     * #### Description
+     * Computes a Hohmann transfer between two circular, coplanar orbits: one burn onto an ellipse
+     * touching both orbits and one burn at its opposite apsis to circularize.
+     *
+     * #### Parameters
+     * - `mu` -- The gravitational parameter of the body orbited.
+     * - `from` -- The radius of the initial orbit in meters.
+     * - `to` -- The radius of the target orbit in meters.
+     *
+     * #### Returns
+     * The magnitudes of both burns in meters per second and the transfer time in seconds.
+     */
+    pub fn hohmann_transfer(mu: f64, from: f64, to: f64) -> ([f64; 2], f64) {
+        let semi_major_axis = (from + to) / 2.0;
+        let first = vis_viva_velocity(mu, from, semi_major_axis) - circular_velocity(mu, from);
+        let second = circular_velocity(mu, to) - vis_viva_velocity(mu, to, semi_major_axis);
+        ([first.abs(), second.abs()], orbital_period(mu, semi_major_axis) / 2.0)
+    }
+
+    /**
+     * #### Description
+     * Computes a bi-elliptic transfer between two circular, coplanar orbits: a burn onto an ellipse
+     * reaching out to an intermediate apoapsis, a burn there onto a second ellipse touching the
+     * target orbit and a burn to circularize.
+     *
+     * #### Remarks
+     * Needs less delta-v than a Hohmann transfer if the radii differ by more than a factor of
+     * about 11.94 and the intermediate apoapsis is high enough, at the cost of a longer transfer.
+     *
+     * #### Parameters
+     * - `mu` -- The gravitational parameter of the body orbited.
+     * - `from` -- The radius of the initial orbit in meters.
+     * - `to` -- The radius of the target orbit in meters.
+     * - `apoapsis` -- The radius of the intermediate apoapsis in meters.
+     *
+     * #### Returns
+     * The magnitudes of all three burns in meters per second and the transfer time in seconds.
+     */
+    pub fn bi_elliptic_transfer(mu: f64, from: f64, to: f64, apoapsis: f64) -> ([f64; 3], f64) {
+        let first_semi_major_axis = (from + apoapsis) / 2.0;
+        let second_semi_major_axis = (to + apoapsis) / 2.0;
+        let first = vis_viva_velocity(mu, from, first_semi_major_axis) - circular_velocity(mu, from);
+        let second = vis_viva_velocity(mu, apoapsis, second_semi_major_axis) - vis_viva_velocity(mu, apoapsis, first_semi_major_axis);
+        let third = circular_velocity(mu, to) - vis_viva_velocity(mu, to, second_semi_major_axis);
+        let duration = (orbital_period(mu, first_semi_major_axis) + orbital_period(mu, second_semi_major_axis)) / 2.0;
+        ([first.abs(), second.abs(), third.abs()], duration)
+    }
+
+    /**
+     * #### Description
+     * Solves Kepler's equation `M = E - e * sin(E)` for the eccentric anomaly of an elliptic orbit.
+     *
+     * #### Remarks
+     * Uses Newton's method, which converges within a few iterations for eccentricities below 1.
+     * Fails with the last estimate if it does not within [KEPLER_MAX_ITERATIONS].
+     *
+     * #### Parameters
+     * - `mean_anomaly` -- The mean anomaly in radians.
+     * - `eccentricity` -- The eccentricity of the orbit, from 0 to 1 (exclusive).
+     *
+     * #### Returns
+     * The eccentric anomaly in radians.
+     */
+    pub fn solve_kepler_elliptic(mean_anomaly: f64, eccentricity: f64) -> Result<f64, KeplerNotConverged> {
+        let wrapped_mean_anomaly = mean_anomaly.rem_euclid(std::f64::consts::TAU);
+        let mut eccentric_anomaly = if eccentricity < 0.8 { wrapped_mean_anomaly } else { std::f64::consts::PI };
+        let mut delta = f64::NAN;
+        for _ in 0..KEPLER_MAX_ITERATIONS {
+            delta = (eccentric_anomaly - eccentricity * eccentric_anomaly.sin() - wrapped_mean_anomaly)
+                / (1.0 - eccentricity * eccentric_anomaly.cos());
+            eccentric_anomaly -= delta;
+            if delta.abs() < KEPLER_TOLERANCE {
+                return Ok(eccentric_anomaly);
+            }
+        }
+        Err(KeplerNotConverged { mean_anomaly, eccentricity, anomaly: eccentric_anomaly, step: delta })
+    }
+
+    /**
+     * #### Description
+     * Solves the hyperbolic Kepler equation `M = e * sinh(H) - H` for the hyperbolic anomaly.
+     *
+     * #### Parameters
+     * - `mean_anomaly` -- The hyperbolic mean anomaly in radians.
+     * - `eccentricity` -- The eccentricity of the orbit, above 1.
+     *
+     * #### Returns
+     * The hyperbolic anomaly.
+     */
+    pub fn solve_kepler_hyperbolic(mean_anomaly: f64, eccentricity: f64) -> Result<f64, KeplerNotConverged> {
+        let mut hyperbolic_anomaly = (2.0 * mean_anomaly / eccentricity).asinh();
+        let mut delta = f64::NAN;
+        for _ in 0..KEPLER_MAX_ITERATIONS {
+            delta = (eccentricity * hyperbolic_anomaly.sinh() - hyperbolic_anomaly - mean_anomaly)
+                / (eccentricity * hyperbolic_anomaly.cosh() - 1.0);
+            hyperbolic_anomaly -= delta;
+            if delta.abs() < KEPLER_TOLERANCE {
+                return Ok(hyperbolic_anomaly);
+            }
+        }
+        Err(KeplerNotConverged { mean_anomaly, eccentricity, anomaly: hyperbolic_anomaly, step: delta })
+    }
+
+    /**
+     * #### Description
+     * Computes the true anomaly from the mean anomaly, for elliptic and hyperbolic orbits.
+     *
+     * #### Parameters
+     * - `mean_anomaly` -- The mean anomaly in radians.
+     * - `eccentricity` -- The eccentricity of the orbit, not 1.
+     *
+     * #### Returns
+     * The true anomaly in radians. If Kepler's equation did not converge, the error holds the true
+     * anomaly of the last estimate.
+     */
+    pub fn true_anomaly_from_mean(mean_anomaly: f64, eccentricity: f64) -> Result<f64, KeplerNotConverged> {
+        let to_true = |anomaly: f64| if eccentricity < 1.0 {
+            2.0 * ((1.0 + eccentricity).sqrt() * (anomaly / 2.0).sin())
+                .atan2((1.0 - eccentricity).sqrt() * (anomaly / 2.0).cos())
+        } else {
+            2.0 * (((eccentricity + 1.0) / (eccentricity - 1.0)).sqrt() * (anomaly / 2.0).tanh()).atan()
+        };
+        let anomaly = if eccentricity < 1.0 {
+            solve_kepler_elliptic(mean_anomaly, eccentricity)
+        } else {
+            solve_kepler_hyperbolic(mean_anomaly, eccentricity)
+        };
+        anomaly
+            .map(to_true)
+            .map_err(|error| KeplerNotConverged { anomaly: to_true(error.anomaly), ..error })
+    }
+
+    /**
+     * #### Description
+     * Computes the mean anomaly from the true anomaly, for elliptic and hyperbolic orbits.
+     *
+     * #### Parameters
+     * - `true_anomaly` -- The true anomaly in radians.
+     * - `eccentricity` -- The eccentricity of the orbit, not 1.
+     *
+     * #### Returns
+     * The mean anomaly in radians.
+     */
+    pub fn mean_anomaly_from_true(true_anomaly: f64, eccentricity: f64) -> f64 {
+        if eccentricity < 1.0 {
+            let eccentric_anomaly = 2.0 * ((1.0 - eccentricity).sqrt() * (true_anomaly / 2.0).sin())
+                .atan2((1.0 + eccentricity).sqrt() * (true_anomaly / 2.0).cos());
+            eccentric_anomaly - eccentricity * eccentric_anomaly.sin()
+        } else {
+            let hyperbolic_anomaly = 2.0 * (((eccentricity - 1.0) / (eccentricity + 1.0)).sqrt() * (true_anomaly / 2.0).tan()).atanh();
+            eccentricity * hyperbolic_anomaly.sinh() - hyperbolic_anomaly
+        }
+    }
+
+    /**
+     * #### Description
+     * Computes the state vectors of a body from its classical orbital elements.
+     *
+     * #### Remarks
+     * The reference plane is the XY plane with +Z pointing north, the reference direction is +X.
+     *
+     * #### Parameters
+     * - `mu` -- The gravitational parameter of the central body in meters cubed per second squared.
+     * - `semi_major_axis` -- The semi-major axis in meters, negative for hyperbolic orbits.
+     * - `eccentricity` -- The eccentricity, not 1.
+     * - `inclination` -- The inclination in radians.
+     * - `longitude_of_ascending_node` -- The longitude of the ascending node in radians.
+     * - `argument_of_periapsis` -- The argument of periapsis in radians.
+     * - `true_anomaly` -- The true anomaly in radians.
+     *
+     * #### Returns
+     * The position in meters and velocity in meters per second, relative to the central body.
+     */
+    pub fn state_from_elements(
+        mu: f64,
+        semi_major_axis: f64,
+        eccentricity: f64,
+        inclination: f64,
+        longitude_of_ascending_node: f64,
+        argument_of_periapsis: f64,
+        true_anomaly: f64,
+    ) -> (DVec3, DVec3) {
+        let semi_latus_rectum = semi_major_axis * (1.0 - eccentricity * eccentricity);
+        let distance = semi_latus_rectum / (1.0 + eccentricity * true_anomaly.cos());
+        let speed = (mu / semi_latus_rectum).sqrt();
+        let rotation = DQuat::from_rotation_z(longitude_of_ascending_node)
+            * DQuat::from_rotation_x(inclination)
+            * DQuat::from_rotation_z(argument_of_periapsis);
+        (
+            rotation * DVec3::new(distance * true_anomaly.cos(), distance * true_anomaly.sin(), 0.0),
+            rotation * DVec3::new(-speed * true_anomaly.sin(), speed * (eccentricity + true_anomaly.cos()), 0.0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{PI, TAU};
+    use super::double::*;
+
+    const ECCENTRICITIES: [f64; 5] = [0.0, 0.5, 0.99, 1.5, 5.0];
+
+    /// The difference of two angles, wrapped into [-PI, PI).
+    fn angle_difference(a: f64, b: f64) -> f64 {
+        (a - b + PI).rem_euclid(TAU) - PI
+    }
+
+    #[test]
+    fn mean_to_true_to_mean_round_trips() {
+        for eccentricity in ECCENTRICITIES {
+            // Closed orbits wrap the mean anomaly, open ones do not.
+            let range = if eccentricity < 1.0 { PI } else { 50.0 };
+            for step in -100..=100 {
+                let mean_anomaly = range * step as f64 / 100.0;
+                let true_anomaly = true_anomaly_from_mean(mean_anomaly, eccentricity)
+                    .unwrap_or_else(|error| panic!("{}", error));
+                let round_trip = mean_anomaly_from_true(true_anomaly, eccentricity);
+                let error = if eccentricity < 1.0 { angle_difference(round_trip, mean_anomaly) } else { round_trip - mean_anomaly };
+                assert!(error.abs() < 1e-9 * mean_anomaly.abs().max(1.0), "e = {}, M = {}: got back {}", eccentricity, mean_anomaly, round_trip);
+            }
+        }
+    }
+
+    #[test]
+    fn kepler_solvers_solve_keplers_equation() {
+        for eccentricity in ECCENTRICITIES {
+            for step in -100..=100 {
+                let mean_anomaly = 0.3 * step as f64;
+                if eccentricity < 1.0 {
+                    let eccentric_anomaly = solve_kepler_elliptic(mean_anomaly, eccentricity).unwrap();
+                    let residual = eccentric_anomaly - eccentricity * eccentric_anomaly.sin() - mean_anomaly;
+                    assert!(angle_difference(residual, 0.0).abs() < 1e-10, "e = {}, M = {}", eccentricity, mean_anomaly);
+                } else {
+                    let hyperbolic_anomaly = solve_kepler_hyperbolic(mean_anomaly, eccentricity).unwrap();
+                    let residual = eccentricity * hyperbolic_anomaly.sinh() - hyperbolic_anomaly - mean_anomaly;
+                    assert!(residual.abs() < 1e-9 * mean_anomaly.abs().max(1.0), "e = {}, M = {}", eccentricity, mean_anomaly);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn kepler_solvers_report_non_convergence() {
+        let error = solve_kepler_elliptic(1.0, f64::NAN).unwrap_err();
+        assert_eq!(error.mean_anomaly, 1.0);
+        assert!(solve_kepler_hyperbolic(f64::INFINITY, 2.0).is_err());
+        assert!(true_anomaly_from_mean(1.0, f64::NAN).is_err());
+    }
+}
@@ -42,6 +42,16 @@ fn build(&self, app: &mut App) {
 #[derive(Default, Component)]
 pub struct Player;
 
+/// The direction the player stands up in, turned against the gravity of the volume the player is in.
+#[derive(Component)]
+pub struct PlayerUp(pub Vector);
+
+impl Default for PlayerUp {
+    fn default() -> Self {
+        Self(Vector::Y)
+    }
+}
+
 #[derive(Event)]
 pub enum MovementAction {
     Move(Vector),
@@ -159,6 +169,7 @@ pub fn setup_player(
         grid_cell,
         TransformBundle::from(Transform::from_translation(translation + Vec3::Y * 1.0)),
         Player,
+        PlayerUp::default(),
         FloatingOrigin,
         MovementAcceleration(30.0),
         MovementDampingFactor(0.92),
@@ -180,15 +191,15 @@ pub fn setup_player(
 fn update_grounded(
     mut commands: Commands,
     mut query: Query<
-        (Entity, &ShapeHits, &Rotation),
+        (Entity, &ShapeHits, &Rotation, &PlayerUp),
         With<Player>,
     >,
 ) {
-    for (entity, hits, rotation) in &mut query {
+    for (entity, hits, rotation, up) in &mut query {
         // The character is grounded if the shape caster has a hit with a normal
         // that isn't too steep.
         let is_grounded = hits.iter().any(|hit| {
-            hit.time_of_impact < 0.0001 && rotation.rotate(-hit.normal2).angle_between(Vector::Y).abs() <= MAX_SLOPE_ANGLE_F64
+            hit.time_of_impact < 0.0001 && rotation.rotate(-hit.normal2).angle_between(up.0).abs() <= MAX_SLOPE_ANGLE_F64
         });
 
         if is_grounded {
@@ -199,11 +210,11 @@ fn update_grounded(
     }
 }
 
-fn apply_movement_damping(mut query: Query<(&MovementDampingFactor, &mut LinearVelocity)>) {
-    for (damping_factor, mut linear_velocity) in &mut query {
-        // We could use `LinearDamping`, but we don't want to dampen movement along the Y axis
-        linear_velocity.x *= damping_factor.0;
-        linear_velocity.z *= damping_factor.0;
+fn apply_movement_damping(mut query: Query<(&MovementDampingFactor, &PlayerUp, &mut LinearVelocity)>) {
+    for (damping_factor, up, mut linear_velocity) in &mut query {
+        // We could use `LinearDamping`, but we don't want to dampen movement along the up axis
+        let vertical = up.0 * linear_velocity.dot(up.0);
+        linear_velocity.0 = vertical + (linear_velocity.0 - vertical) * damping_factor.0;
     }
 }
 
@@ -213,6 +224,7 @@ fn movement(
     mut controllers: Query<(
         &MovementAcceleration,
         &JumpImpulse,
+        &PlayerUp,
         &mut LinearVelocity,
         &mut AngularVelocity,
         Has<Grounded>,
@@ -221,18 +233,19 @@ fn movement(
     let delta_time = time.delta_seconds_f64();
 
     for event in movement_event_reader.read() {
-        for (movement_acceleration, jump_impulse, mut linear_velocity, mut angular_velocity, is_grounded) in
+        for (movement_acceleration, jump_impulse, up, mut linear_velocity, mut angular_velocity, is_grounded) in
         &mut controllers
         {
             if is_grounded {
                 match event {
                     MovementAction::Move(direction) => {
-                        linear_velocity.x += direction.x * movement_acceleration.0 * delta_time;
-                        linear_velocity.y -= direction.y * movement_acceleration.0 * delta_time;
-                        linear_velocity.z -= direction.z * movement_acceleration.0 * delta_time;
+                        let frame = Quaternion::from_rotation_arc(Vector::Y, up.0);
+                        let local = Vector::new(direction.x, -direction.y, -direction.z);
+                        linear_velocity.0 += frame * local * movement_acceleration.0 * delta_time;
                     }
                     MovementAction::Jump => {
-                        linear_velocity.y = jump_impulse.0;
+                        let vertical = linear_velocity.dot(up.0);
+                        linear_velocity.0 += up.0 * (jump_impulse.0 - vertical);
                     }
                     MovementAction::Rotate(direction) => {
                         angular_velocity.0 = angular_velocity.0 * *direction;
@@ -259,6 +272,7 @@ fn kinematic_controller_collisions(
             &mut Position,
             &Rotation,
             &mut LinearVelocity,
+            &PlayerUp,
         ),
         With<Player>,
     >,
@@ -280,7 +294,7 @@ fn kinematic_controller_collisions(
         // Get the body of the character controller and whether it is the first
         // or second entity in the collision.
         let is_first: bool;
-        let (rb, mut position, rotation, mut linear_velocity) =
+        let (rb, mut position, rotation, mut linear_velocity, up) =
             if let Ok(character) = character_controllers.get_mut(collider_parent1.get()) {
                 is_first = true;
                 character
@@ -312,9 +326,10 @@ fn kinematic_controller_collisions(
 
             // If the slope isn't too steep to walk on but the character
             // is falling, reset vertical velocity.
-            if normal.angle_between(Vector::Y).abs() <= MAX_SLOPE_ANGLE_F64 && linear_velocity.y < 0.0
+            let vertical = linear_velocity.dot(up.0);
+            if normal.angle_between(up.0).abs() <= MAX_SLOPE_ANGLE_F64 && vertical < 0.0
             {
-                linear_velocity.y = linear_velocity.y.max(0.0);
+                linear_velocity.0 -= up.0 * vertical;
             }
         }
     }
@@ -2,30 +2,58 @@
 use log::info;
 use bevy::math::cubic_splines::Point;
 use bevy::app::{App, Main, Plugin, PostUpdate, Startup, Update};
-use bevy::asset::Assets;
+use bevy::asset::{AssetServer, Assets, Handle};
 use bevy::math::{DVec3, dvec3, I64Vec3, IVec3, Vec3, Vec3A};
 use bevy::pbr::{PbrBundle, StandardMaterial};
-use bevy::prelude::{Color, Commands, Component, Entity, Gizmos, GlobalTransform, Image, Mesh, Mut, Query, Res, ResMut, Resource, Time, Transform, With, Without};
+use bevy::prelude::{warn, Color, Commands, Component, Entity, Gizmos, GlobalTransform, Has, Image, Local, Mesh, Mut, Query, Res, ResMut, Resource, Time, Transform, With, Without};
 use bevy::prelude::shape::UVSphere;
 use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
-use bevy::utils::default;
+use bevy::utils::{default, HashMap};
 use big_space::{FloatingOrigin, FloatingOriginSettings, GridCell};
+use crate::solarsystem::definition::SolarSystemAsset;
+use crate::solarsystem::nbody::NBody;
+use crate::solarsystem::soi::*;
+use crate::solarsystem::time_warp::*;
+use crate::solarsystem::trajectory::*;
 use crate::bevy_stupid::{dvec3_to_vec3, vec3_to_dvec3};
 use crate::common_math::distance3_f64;
 use crate::physics_math::{double, single};
 use rand::Rng;
 
+pub mod barnes_hut;
+pub mod definition;
+pub mod maneuver;
+pub mod nbody;
+pub mod soi;
+pub mod time_warp;
+pub mod trajectory;
+
 pub struct PlanetsPlugin;
 
 impl Plugin for PlanetsPlugin {
     fn build(&self, app: &mut App) {
         app
-            .insert_resource(SimulationSpeed(86400.0))
-            //.insert_resource(SimulationSpeed(1.0))
-            //.insert_resource(SimulationSpeed(1000.0))
-            .add_systems(Startup, (setup_planets))
-            .add_systems(Update, (update_planets))
-            .add_systems(PostUpdate, (post_update_planets, log_planets));
+            .init_resource::<TimeWarp>()
+            .init_resource::<SimulationTime>()
+            .init_resource::<NBodySettings>()
+            .init_resource::<TrajectorySettings>()
+            .add_plugins(definition::Plugin)
+            .add_plugins(maneuver::Plugin)
+            .add_systems(Startup, (setup_planets, setup_time_warp_text))
+            .add_event::<SoiChanged>()
+            .add_systems(Update, (
+                change_time_warp,
+                apply_time_warp,
+                advance_simulation_time,
+                spawn_solar_system,
+                update_planets,
+                propagate_orbits,
+                track_spheres_of_influence,
+                propagate_patched_conics,
+                predict_trajectories,
+                spawn_trajectory_labels,
+            ).chain())
+            .add_systems(PostUpdate, (log_planets, draw_trajectories, update_trajectory_labels, update_time_warp_text));
     }
 }
 
@@ -34,12 +62,18 @@ fn build(&self, app: &mut App) {
 #[derive(Component)]
 pub struct Sun;
 
+/// The atmosphere of a body, reaching `outer_radius` meters from its center.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct BodyAtmosphere {
+    pub outer_radius: f64,
+}
+
 #[derive(Component)]
 pub struct Mass {
     /**
      * The mass of the object.
      */
-    value: f32,
+    value: f64,
 
     /**
      * The center of mass of the object in relation to the object's origin.
@@ -48,13 +82,20 @@ pub struct Mass {
 }
 
 impl Mass {
-    pub fn new(mass: f32) -> Self {
+    pub fn new(mass: f64) -> Self {
         Self {
             value: mass,
             center: Vec3::new(0.0, 0.0, 0.0),
         }
     }
 
+    /**
+     * Gets the mass in kilograms.
+     */
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
     pub const fn zero() -> Self {
         Self {
             value: 0.0,
@@ -83,8 +124,8 @@ pub fn acceleration_f64(&self, other: &Mass, position_self: DVec3, position_othe
             return DVec3::new(0.0, 0.0, 0.0);
         }
         let unit: DVec3 = distance.normalize();
-        let force = double::compute_gravitational_force(self.value as f64, other.value as f64, distance.length());
-        let acceleration = force / self.value as f64;
+        let force = double::compute_gravitational_force(self.value, other.value, distance.length());
+        let acceleration = force / self.value;
         let acceleration: DVec3 = unit * acceleration;
         acceleration
     }
@@ -93,38 +134,69 @@ pub fn acceleration_f64(&self, other: &Mass, position_self: DVec3, position_othe
 #[derive(Component, Clone, Debug, PartialEq, Ord, PartialOrd, Eq, Hash)]
 pub struct Name(String);
 
+/// The velocity of a body in meters per second.
 #[derive(Component, Copy, Clone, Debug, PartialEq)]
-struct Velocity {
-    velocity: Vec3,
+pub struct Velocity {
+    pub velocity: DVec3,
 }
 
-#[derive(Resource)]
-struct SimulationSpeed(f32);
-
 impl Velocity {
-    fn new(velocity: Vec3) -> Self {
+    pub fn new(velocity: DVec3) -> Self {
         Self {
             velocity,
         }
     }
-    fn zero() -> Self {
+
+    pub fn zero() -> Self {
         Self {
-            velocity: Vec3::new(0.0, 0.0, 0.0),
+            velocity: DVec3::ZERO,
         }
     }
+}
 
-    /**
-     * Apply the velocity to the position, updating the sector if necessary.
-     * @param position The position to update.
-     */
-    fn apply(&mut self, transform: &mut Transform, delta: f32) {
-        let position: Vec3 = transform.translation.clone();
-        let velocity: Vec3 = self.velocity;
-        let new_position = position + velocity * delta;
-        let delta = new_position - position;
-        transform.translation += delta;
+/// The absolute position in meters of a body moved by the n-body integrator. It is authoritative
+/// over the `Transform` and `GridCell` of the body, which lack the precision to accumulate small steps.
+#[derive(Component, Copy, Clone, Debug, PartialEq)]
+pub struct SimulationPosition(pub DVec3);
+
+/// The simulated time in seconds since startup, scaled by the [TimeWarp].
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq)]
+pub struct SimulationTime(pub f64);
+
+fn advance_simulation_time(
+    time: Res<Time>,
+    time_warp: Res<TimeWarp>,
+    mut simulation_time: ResMut<SimulationTime>,
+) {
+    simulation_time.0 += time.delta_seconds_f64() * time_warp.rate();
+}
+
+/// Settings of the n-body integrator.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct NBodySettings {
+    /// The longest simulated time of one integrator step, in seconds.
+    pub max_dt: f64,
+    /// The most integrator steps per frame. Frames needing more use longer steps.
+    pub max_substeps: usize,
+    /// From this many bodies on, gravity is approximated with a Barnes–Hut octree instead of
+    /// summing over all pairs of bodies.
+    pub barnes_hut_min_bodies: usize,
+    /// How small groups of bodies have to appear, their size divided by their distance, to be
+    /// approximated by their center of mass. 0 is exact, larger is faster.
+    pub opening_angle: f64,
+}
+
+impl Default for NBodySettings {
+    fn default() -> Self {
+        Self {
+            max_dt: 3600.0,
+            max_substeps: 1000,
+            barnes_hut_min_bodies: 256,
+            opening_angle: 0.5,
+        }
     }
 }
+
 fn uv_debug_texture() -> Image {
     const TEXTURE_SIZE: usize = 8;
 
@@ -158,68 +230,56 @@ fn uv_debug_texture() -> Image {
     )
 }
 
-fn uv_sun_texture() -> Image {
-    const TEXTURE_SIZE: usize = 8;
-
-    let mut palette: [u8; 32] = [
-        255, 255, 0, 255,
-        255, 255, 0, 255,
-        255, 255, 0, 255,
-        255, 255, 0, 255,
-        255, 255, 0, 255,
-        255, 255, 0, 255,
-        255, 255, 0, 255,
-        255, 255, 0, 255,
-    ];
-
-    let mut texture_data = [0; TEXTURE_SIZE * TEXTURE_SIZE * 4];
-    for y in 0..TEXTURE_SIZE {
-        let offset = TEXTURE_SIZE * y * 4;
-        texture_data[offset..(offset + TEXTURE_SIZE * 4)].copy_from_slice(&palette);
-        palette.rotate_right(4);
-    }
-
-    Image::new_fill(
-        Extent3d {
-            width: TEXTURE_SIZE as u32,
-            height: TEXTURE_SIZE as u32,
-            depth_or_array_layers: 1,
-        },
-        TextureDimension::D2,
-        &texture_data,
-        TextureFormat::Rgba8UnormSrgb,
-    )
-}
-
+/// Integrates the bodies without an [Orbit] under the gravity of all bodies without [MassNoEffect].
+///
+/// Bodies on rails attract with their position at the start of the frame.
 fn update_planets(
-    floating_origin_settings: Res<big_space::FloatingOriginSettings>,
+    floating_origin_settings: Res<FloatingOriginSettings>,
     time: Res<Time>,
-    simulation_speed: Res<SimulationSpeed>,
-    mut query_self: Query<(Entity, &mut Velocity, &Mass, &Transform, &GridCell<i64>)>,
-    mut query2: Query<(Entity, &Mass, &Transform, &GridCell<i64>), (Without<MassNoEffect>)>,
+    time_warp: Res<TimeWarp>,
+    settings: Res<NBodySettings>,
+    mut bodies: Query<(&Mass, &mut Transform, &mut GridCell<i64>, Option<&mut Velocity>, Option<&mut SimulationPosition>, Has<Orbit>, Has<MassNoEffect>)>,
+    mut reported_long_steps: Local<bool>,
 ) {
-    for (id_self, mut velocity_self, mass_self, transform_self, grid_cell_self) in query_self.iter_mut() {
-        let mut velocity_out = velocity_self;
-        for (id_other, mass_other, transform_other, grid_cell_other) in query2.iter_mut() {
-            if id_self == id_other {
-                continue;
-            }
-            let position_self = DVec3::default();
-            let position_other: DVec3 = floating_origin_settings.grid_position_double::<i64>(
-                &(grid_cell_other - grid_cell_self),
-                transform_other,
-            ) - vec3_to_dvec3(transform_self.translation);
-
-            let mut acceleration = mass_self.acceleration_f64(mass_other, position_self, position_other);
-            acceleration *= time.delta_seconds() as f64 * simulation_speed.0 as f64;
-            velocity_out.velocity -= dvec3_to_vec3(acceleration);
+    let duration = time.delta_seconds_f64() * time_warp.rate();
+    let mut states = bodies.iter()
+        .map(|(mass, transform, grid_cell, velocity, position, on_rails, no_effect)| NBody {
+            position: position.map_or_else(|| floating_origin_settings.grid_position_double::<i64>(grid_cell, transform), |position| position.0),
+            velocity: velocity.map_or(DVec3::ZERO, |velocity| velocity.velocity),
+            mu: if no_effect { 0.0 } else { double::gravitational_parameter(mass.value) },
+            moves: velocity.is_some() && !on_rails,
+        })
+        .collect::<Vec<_>>();
+    let opening_angle = settings.opening_angle;
+    let substeps = if states.len() >= settings.barnes_hut_min_bodies {
+        nbody::integrate(&mut states, duration, settings.max_dt, settings.max_substeps, |bodies, accelerations| {
+            barnes_hut::accelerations(bodies, accelerations, opening_angle)
+        })
+    } else {
+        nbody::integrate(&mut states, duration, settings.max_dt, settings.max_substeps, nbody::direct_accelerations)
+    };
+    if substeps > 0 && duration / substeps as f64 > settings.max_dt && !*reported_long_steps {
+        warn!("Simulating {} s per frame takes more than {} steps, the n-body integration gets less precise", duration, settings.max_substeps);
+        *reported_long_steps = true;
+    }
+    for ((_, mut transform, mut grid_cell, velocity, position, ..), state) in bodies.iter_mut().zip(states) {
+        if !state.moves {
+            continue;
+        }
+        if let Some(mut velocity) = velocity {
+            velocity.velocity = state.velocity;
         }
-        velocity_self = velocity_out;
+        if let Some(mut position) = position {
+            position.0 = state.position;
+        }
+        let (new_grid_cell, translation) = floating_origin_settings.translation_to_grid::<i64>(state.position);
+        *grid_cell = new_grid_cell;
+        transform.translation = translation;
     }
 }
 
 fn log_planets(
-    simulation_speed: Res<SimulationSpeed>,
+    time_warp: Res<TimeWarp>,
     mut gizmos: Gizmos,
     floating_origin_settings: Res<FloatingOriginSettings>,
     time: Res<Time>,
@@ -228,7 +288,7 @@ fn log_planets(
     mut sun: Query<(&GlobalTransform, &Transform), With<Sun>>,
 ) {
     // let sim_speed: f64 = (time.delta_seconds() * simulation_speed.0) as f64;
-    let sim_speed: f64 = (simulation_speed.0 * 0.25) as f64;
+    let sim_speed: f64 = time_warp.rate() * 0.25;
     for (id, velocity, grid, transform, glob_transform, opt) in query1.iter_mut() {
 
         // let pos = floating_origin_settings.grid_position::<i64>(grid, transform);
@@ -246,266 +306,182 @@ fn log_planets(
         let distance_scale = if opt.is_some() {10.0} else {100.0};
         gizmos.line(
             translation,
-            dvec3_to_vec3(vec3_to_dvec3(translation) + vec3_to_dvec3(velocity.velocity) * sim_speed * distance_scale),
+            dvec3_to_vec3(vec3_to_dvec3(translation) + velocity.velocity * sim_speed * distance_scale),
             if opt.is_some() {Color::TOMATO} else {Color::CYAN}
         );
         // info!("{}: Grid: {:?}, Position: {:?}, Velocity: {:?}", id.index(), grid, transform.translation, velocity);
     }
 }
 
-fn post_update_planets(
-    time: Res<Time>,
-    simulation_speed: Res<SimulationSpeed>,
-    mut query: Query<(&mut Velocity, &mut Transform)>,
+/// The solar system file loaded on startup.
+pub const SOLAR_SYSTEM_PATH: &str = "solarsystem/sol.system.toml";
+
+/// The solar system the bodies are spawned from, once it is loaded.
+#[derive(Resource)]
+pub struct SolarSystem {
+    pub definition: Handle<SolarSystemAsset>,
+    spawned: bool,
+}
+
+fn spawn_solar_system(
+    mut commands: Commands,
+    mut solar_system: ResMut<SolarSystem>,
+    definitions: Res<Assets<SolarSystemAsset>>,
+    floating_origin_settings: Res<FloatingOriginSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    simulation_time: Res<SimulationTime>,
+) {
+    if solar_system.spawned {
+        return;
+    }
+    let Some(definition) = definitions.get(&solar_system.definition) else {
+        return;
+    };
+    solar_system.spawned = true;
+    let debug_texture = images.add(uv_debug_texture());
+    let states = definition.initial_states();
+    let mut entities: Vec<Entity> = Vec::with_capacity(definition.bodies.len());
+    for (body, (position, velocity)) in definition.bodies.iter().zip(states.iter().copied()) {
+        let material = materials.add(StandardMaterial {
+            base_color: body.color.map_or(Color::WHITE, |[r, g, b]| Color::rgb(r, g, b)),
+            base_color_texture: match (&body.texture_asset, body.color) {
+                (Some(texture), _) => Some(texture.clone()),
+                (None, Some(_)) => None,
+                (None, None) => Some(debug_texture.clone()),
+            },
+            ..default()
+        });
+        let mesh = meshes.add(UVSphere {
+            radius: body.radius as f32,
+            ..default()
+        }.into());
+        let (grid_cell, translation) = floating_origin_settings.translation_to_grid::<i64>(position);
+        let mut entity_commands = commands.spawn((
+            PbrBundle {
+                mesh,
+                material,
+                transform: Transform::from_translation(translation),
+                ..default()
+            },
+            Mass::new(body.mass),
+            Name(body.name.clone()),
+            grid_cell,
+        ));
+        if body.parent.is_none() {
+            entity_commands.insert(Sun);
+        }
+        if let Some(atmosphere_height) = body.atmosphere_height {
+            entity_commands.insert(BodyAtmosphere {
+                outer_radius: body.radius + atmosphere_height,
+            });
+        }
+        if body.parent.is_some() || body.state.is_some() {
+            entity_commands.insert(Velocity::new(velocity));
+        }
+        if body.state.is_some() || (body.parent.is_some() && !body.rails) {
+            entity_commands.insert(SimulationPosition(position));
+        }
+        if let Some(parent) = definition.parent_index(body) {
+            let orbit = Orbit::from_state(
+                entities[parent],
+                double::gravitational_parameter(definition.bodies[parent].mass + body.mass),
+                position - states[parent].0,
+                velocity - states[parent].1,
+                simulation_time.0,
+            );
+            let semi_major_axis = orbit.map_or((position - states[parent].0).length(), |orbit| orbit.semi_major_axis.abs());
+            entity_commands.insert(SphereOfInfluence {
+                radius: double::sphere_of_influence_radius(semi_major_axis, body.mass, definition.bodies[parent].mass),
+            });
+            entity_commands.insert(TrajectoryPrediction::new(Color::CYAN));
+            if !body.rails {
+                entity_commands.insert(SoiTracked);
+            }
+            match orbit {
+                Some(orbit) if body.rails => {
+                    entity_commands.insert(orbit);
+                }
+                None if body.rails => warn!("Body '{}' has no orbit around '{}' to follow on rails", body.name, definition.bodies[parent].name),
+                _ => {}
+            }
+        } else {
+            entity_commands.insert(SphereOfInfluence {
+                radius: f64::INFINITY,
+            });
+        }
+        entities.push(entity_commands.id());
+    }
+}
+
+/// Moves bodies with an [Orbit] along it, relative to the current position of their parent.
+fn propagate_orbits(
+    floating_origin_settings: Res<FloatingOriginSettings>,
+    simulation_time: Res<SimulationTime>,
+    mut bodies: Query<(Entity, Option<&Orbit>, &mut Transform, &mut GridCell<i64>, Option<&mut Velocity>), With<Mass>>,
 ) {
-    for (mut velocity, mut transform) in query.iter_mut() {
-        velocity.apply(&mut transform, time.delta_seconds() * simulation_speed.0);
+    let orbits = bodies.iter()
+        .filter_map(|(entity, orbit, ..)| orbit.map(|orbit| (entity, *orbit)))
+        .collect::<HashMap<_, _>>();
+    // Parents move before their children, so bodies are ordered by how many orbits they are nested in.
+    let depth = |mut entity: Entity| {
+        let mut depth = 0;
+        while let Some(orbit) = orbits.get(&entity) {
+            depth += 1;
+            entity = orbit.parent;
+            if depth > orbits.len() {
+                break;
+            }
+        }
+        depth
+    };
+    let mut order = orbits.keys().copied().collect::<Vec<_>>();
+    order.sort_by_cached_key(|entity| depth(*entity));
+
+    let mut states: HashMap<Entity, (DVec3, DVec3)> = HashMap::new();
+    for entity in order {
+        let orbit = &orbits[&entity];
+        let parent_state = match states.get(&orbit.parent) {
+            Some(parent_state) => *parent_state,
+            None => match bodies.get(orbit.parent) {
+                Ok((_, _, transform, grid_cell, velocity)) => (
+                    floating_origin_settings.grid_position_double::<i64>(grid_cell, transform),
+                    velocity.map_or(DVec3::ZERO, |velocity| velocity.velocity),
+                ),
+                Err(_) => continue,
+            },
+        };
+        let (position, velocity) = orbit.state_at(simulation_time.0);
+        let state = (parent_state.0 + position, parent_state.1 + velocity);
+        states.insert(entity, state);
+        let Ok((_, _, mut transform, mut grid_cell, body_velocity)) = bodies.get_mut(entity) else {
+            continue;
+        };
+        let (new_grid_cell, translation) = floating_origin_settings.translation_to_grid::<i64>(state.0);
+        *grid_cell = new_grid_cell;
+        transform.translation = translation;
+        if let Some(mut body_velocity) = body_velocity {
+            body_velocity.velocity = state.1;
+        }
     }
 }
 
 fn setup_planets(
     mut commands: Commands,
-    mut floating_origin_settings: Res<big_space::FloatingOriginSettings>,
+    asset_server: Res<AssetServer>,
+    floating_origin_settings: Res<big_space::FloatingOriginSettings>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut images: ResMut<Assets<Image>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    // Sun
-    let scale = Vec3::new(1.0, 1.0, 1.0);
-    let radius_m = 695700e3;
-    let mass_kg = 1.989e30;
-    let distance_to_sun_m = 0.0;
-    let escape_velocity_mps = 0.0;
-    let material = materials.add(StandardMaterial {
-        base_color_texture: Some(images.add(uv_sun_texture())),
-        ..default()
-    });
-    let mesh = meshes.add(UVSphere {
-        radius: radius_m,
-        ..default()
-    }.into());
-    commands.spawn((
-        PbrBundle {
-            mesh,
-            material,
-            transform: Transform::from_xyz(0.0, 0.0, 0.0),
-            ..default()
-        },
-        Mass::new(mass_kg),
-        Name("Sun".to_string()),
-        Sun,
-        GridCell::<i64>::default(),
-    ));
-
-    // Earth
-    let material = materials.add(StandardMaterial {
-        base_color_texture: Some(images.add(uv_debug_texture())),
-        ..default()
-    });
-    let radius_m = 6378137.0;
-    let mass_kg = 5.9722e24;
-    let distance_to_sun_m = 149.6e9;
-    let escape_velocity_mps = 11186.0;
-    let mesh = meshes.add(UVSphere {
-        radius: radius_m,
-        ..default()
-    }.into());
-    commands.spawn((
-        PbrBundle {
-            mesh,
-            material,
-            transform: Transform::from_translation(floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).1).with_scale(scale),
-            ..default()
-        },
-        Mass::new(mass_kg),
-        Name("Earth".to_string()),
-        Velocity::new(Vec3::new(0.0, escape_velocity_mps, 0.0)),
-        floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).0,
-    ));
-
-    // Mars
-    let material = materials.add(StandardMaterial {
-        base_color_texture: Some(images.add(uv_debug_texture())),
-        ..default()
-    });
-    let radius_m = 3389500.0;
-    let mass_kg = 6.4171e23;
-    let distance_to_sun_m = 227.9e9;
-    let escape_velocity_mps = 5027.0;
-    let mesh = meshes.add(UVSphere {
-        radius: radius_m,
-        ..default()
-    }.into());
-    commands.spawn((
-        PbrBundle {
-            mesh,
-            material,
-            transform: Transform::from_translation(floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).1).with_scale(scale),
-            ..default()
-        },
-        Mass::new(mass_kg),
-        Name("Mars".to_string()),
-        Velocity::new(Vec3::new(0.0, escape_velocity_mps, 0.0)),
-        floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).0,
-    ));
-
-    // Mercury
-    let material = materials.add(StandardMaterial {
-        base_color_texture: Some(images.add(uv_debug_texture())),
-        ..default()
-    });
-    let radius_m = 2439700.0;
-    let mass_kg = 3.3011e23;
-    let distance_to_sun_m = 57.9e9;
-    let escape_velocity_mps = 4276.0;
-    let mesh = meshes.add(UVSphere {
-        radius: radius_m,
-        ..default()
-    }.into());
-    commands.spawn((
-        PbrBundle {
-            mesh,
-            material,
-            transform: Transform::from_translation(floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).1).with_scale(scale),
-            ..default()
-        },
-        Mass::new(mass_kg),
-        Name("Mercury".to_string()),
-        Velocity::new(Vec3::new(0.0, escape_velocity_mps, 0.0)),
-        floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).0,
-    ));
-
-    // Venus
-    let material = materials.add(StandardMaterial {
-        base_color_texture: Some(images.add(uv_debug_texture())),
-        ..default()
+    commands.insert_resource(SolarSystem {
+        definition: asset_server.load(SOLAR_SYSTEM_PATH),
+        spawned: false,
     });
-    let radius_m = 6051800.0;
-    let mass_kg = 4.8675e24;
-    let distance_to_sun_m = 108.2e9;
-    let escape_velocity_mps = 10360.0;
-    let mesh = meshes.add(UVSphere {
-        radius: radius_m,
-        ..default()
-    }.into());
-    commands.spawn((
-        PbrBundle {
-            mesh,
-            material,
-            transform: Transform::from_translation(floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).1).with_scale(scale),
-            ..default()
-        },
-        Mass::new(mass_kg),
-        Name("Venus".to_string()),
-        Velocity::new(Vec3::new(0.0, escape_velocity_mps, 0.0)),
-        floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).0,
-    ));
-
-    // Jupiter
-    let material = materials.add(StandardMaterial {
-        base_color_texture: Some(images.add(uv_debug_texture())),
-        ..default()
-    });
-    let radius_m = 69911000.0;
-    let mass_kg = 1.8982e27;
-    let distance_to_sun_m = 778.6e9;
-    let escape_velocity_mps = 59500.0;
-    let mesh = meshes.add(UVSphere {
-        radius: radius_m,
-        ..default()
-    }.into());
-    commands.spawn((
-        PbrBundle {
-            mesh,
-            material,
-            transform: Transform::from_translation(floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).1).with_scale(scale),
-            ..default()
-        },
-        Mass::new(mass_kg),
-        Name("Jupiter".to_string()),
-        Velocity::new(Vec3::new(0.0, escape_velocity_mps, 0.0)),
-        floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).0,
-    ));
-
-    // Saturn
-    let material = materials.add(StandardMaterial {
-        base_color_texture: Some(images.add(uv_debug_texture())),
-        ..default()
-    });
-    let radius_m = 58232000.0;
-    let mass_kg = 5.6834e26;
-    let distance_to_sun_m = 1433.5e9;
-    let escape_velocity_mps = 35200.0;
-    let mesh = meshes.add(UVSphere {
-        radius: radius_m,
-        ..default()
-    }.into());
-    commands.spawn((
-        PbrBundle {
-            mesh,
-            material,
-            transform: Transform::from_translation(floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).1).with_scale(scale),
-            ..default()
-        },
-        Mass::new(mass_kg),
-        Name("Saturn".to_string()),
-        Velocity::new(Vec3::new(0.0, escape_velocity_mps, 0.0)),
-        floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).0,
-    ));
-
-    // Uranus
-    let material = materials.add(StandardMaterial {
-        base_color_texture: Some(images.add(uv_debug_texture())),
-        ..default()
-    });
-    let radius_m = 25362000.0;
-    let mass_kg = 8.6810e25;
-    let distance_to_sun_m = 2872.5e9;
-    let escape_velocity_mps = 21300.0;
-    let mesh = meshes.add(UVSphere {
-        radius: radius_m,
-        ..default()
-    }.into());
-    commands.spawn((
-        PbrBundle {
-            mesh,
-            material,
-            transform: Transform::from_translation(floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).1).with_scale(scale),
-            ..default()
-        },
-        Mass::new(mass_kg),
-        Name("Uranus".to_string()),
-        Velocity::new(Vec3::new(0.0, escape_velocity_mps, 0.0)),
-        floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).0,
-    ));
-
-    // Neptune
-    let material = materials.add(StandardMaterial {
-        base_color_texture: Some(images.add(uv_debug_texture())),
-        ..default()
-    });
-    let radius_m = 24622000.0;
-    let mass_kg = 1.0241e26;
-    let distance_to_sun_m = 4495.1e9;
-    let escape_velocity_mps = 24000.0;
-    let mesh = meshes.add(UVSphere {
-        radius: radius_m,
-        ..default()
-    }.into());
-    commands.spawn((
-        PbrBundle {
-            mesh,
-            material,
-            transform: Transform::from_translation(floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).1).with_scale(scale),
-            ..default()
-        },
-        Mass::new(mass_kg),
-        Name("Neptune".to_string()),
-        Velocity::new(Vec3::new(0.0, escape_velocity_mps, 0.0)),
-        floating_origin_settings.translation_to_grid::<i64>(DVec3::new(0.0, 0.0, -distance_to_sun_m)).0,
-    ));
 
     // Spawn a random amount of asteroids at a random distance and velocity around the sun.
+    let scale = Vec3::new(1.0, 1.0, 1.0);
     let mut rng = rand::thread_rng();
     let num_asteroids = rng.gen_range(10..20);
     for no in 0..num_asteroids {
@@ -534,7 +510,8 @@ fn setup_planets(
             Mass::new(mass_kg),
             MassNoEffect,
             Name(format!("Asteroid {}", no).to_string()),
-            Velocity::new(Vec3::new(escape_velocity_mps_x, escape_velocity_mps_y, escape_velocity_mps_z)),
+            Velocity::new(DVec3::new(escape_velocity_mps_x, escape_velocity_mps_y, escape_velocity_mps_z)),
+            SimulationPosition(DVec3::new(distance_to_sun_m_x, distance_to_sun_m_y, distance_to_sun_m_z)),
             floating_origin_settings.translation_to_grid::<i64>(DVec3::new(distance_to_sun_m_x, distance_to_sun_m_y, distance_to_sun_m_z)).0,
         ));
     }
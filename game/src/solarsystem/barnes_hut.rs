@@ -0,0 +1,234 @@
+use bevy::math::DVec3;
+use bevy::tasks::{ComputeTaskPool, TaskPool};
+use crate::solarsystem::nbody::NBody;
+
+/// Nodes this deep are not split any further, their bodies share one leaf.
+const MAX_DEPTH: usize = 48;
+
+/// Bodies per task when computing accelerations in parallel.
+const BODIES_PER_TASK: usize = 256;
+
+struct OctreeNode {
+    center: DVec3,
+    half_size: f64,
+    /// The number of bodies within.
+    count: usize,
+    /// The summed gravitational parameter of all bodies within.
+    mu: f64,
+    /// The sum of `mu * position` of all bodies within, divided by `mu` once the tree is built.
+    center_of_mass: DVec3,
+    /// The index of the first of eight children, `None` for leaves.
+    children: Option<usize>,
+    /// The only body of a leaf. Leaves at [MAX_DEPTH] may hold more bodies and keep none.
+    body: Option<usize>,
+}
+
+impl OctreeNode {
+    fn new(center: DVec3, half_size: f64) -> Self {
+        Self {
+            center,
+            half_size,
+            count: 0,
+            mu: 0.0,
+            center_of_mass: DVec3::ZERO,
+            children: None,
+            body: None,
+        }
+    }
+
+    fn octant(&self, position: DVec3) -> usize {
+        (position.x >= self.center.x) as usize
+            | ((position.y >= self.center.y) as usize) << 1
+            | ((position.z >= self.center.z) as usize) << 2
+    }
+
+    /// Whether the position lies within the node, bounds like [Self::octant] assigns them.
+    fn contains(&self, position: DVec3) -> bool {
+        position.cmpge(self.center - self.half_size).all() && position.cmplt(self.center + self.half_size).all()
+    }
+}
+
+/// An octree over the attracting bodies, approximating distant groups of bodies by their center
+/// of mass.
+///
+/// Positions are absolute, as resolved from the big_space grid cells of the bodies, so the tree
+/// spans the whole simulation regardless of the floating origin.
+pub struct Octree {
+    nodes: Vec<OctreeNode>,
+}
+
+impl Octree {
+    pub fn build(bodies: &[NBody]) -> Self {
+        let attracting = || bodies.iter().enumerate().filter(|(_, body)| body.mu != 0.0);
+        let (min, max) = attracting().fold((DVec3::splat(f64::MAX), DVec3::splat(f64::MIN)), |(min, max), (_, body)| {
+            (min.min(body.position), max.max(body.position))
+        });
+        let mut tree = Self { nodes: vec![] };
+        if min.x > max.x {
+            return tree;
+        }
+        let half_size = ((max - min).max_element() / 2.0).max(1.0) * 1.001;
+        tree.nodes.push(OctreeNode::new((min + max) / 2.0, half_size));
+        for (index, body) in attracting() {
+            tree.insert(bodies, index, body);
+        }
+        for node in tree.nodes.iter_mut().filter(|node| node.mu != 0.0) {
+            node.center_of_mass /= node.mu;
+        }
+        tree
+    }
+
+    fn insert(&mut self, bodies: &[NBody], index: usize, body: &NBody) {
+        let mut node = 0;
+        for depth in 0.. {
+            self.nodes[node].count += 1;
+            self.nodes[node].mu += body.mu;
+            self.nodes[node].center_of_mass += body.position * body.mu;
+            if let Some(children) = self.nodes[node].children {
+                node = children + self.nodes[node].octant(body.position);
+                continue;
+            }
+            let Some(existing) = self.nodes[node].body else {
+                // The leaf was empty, unless it is at the maximum depth and already holds several bodies.
+                if self.nodes[node].count == 1 {
+                    self.nodes[node].body = Some(index);
+                }
+                return;
+            };
+            if depth >= MAX_DEPTH {
+                self.nodes[node].body = None;
+                return;
+            }
+            self.split(node);
+            self.nodes[node].body = None;
+            let existing_body = &bodies[existing];
+            let child = self.nodes[node].children.unwrap() + self.nodes[node].octant(existing_body.position);
+            self.nodes[child].count = 1;
+            self.nodes[child].mu = existing_body.mu;
+            self.nodes[child].center_of_mass = existing_body.position * existing_body.mu;
+            self.nodes[child].body = Some(existing);
+            node = self.nodes[node].children.unwrap() + self.nodes[node].octant(body.position);
+        }
+    }
+
+    fn split(&mut self, node: usize) {
+        let (center, half_size) = (self.nodes[node].center, self.nodes[node].half_size / 2.0);
+        self.nodes[node].children = Some(self.nodes.len());
+        for octant in 0..8 {
+            let offset = DVec3::new(
+                if octant & 1 != 0 { half_size } else { -half_size },
+                if octant & 2 != 0 { half_size } else { -half_size },
+                if octant & 4 != 0 { half_size } else { -half_size },
+            );
+            self.nodes.push(OctreeNode::new(center + offset, half_size));
+        }
+    }
+
+    /// Computes the acceleration of `bodies[index]`. Nodes appearing smaller than `opening_angle`
+    /// (their size divided by their distance) are treated as a single mass.
+    ///
+    /// An attracting body is part of the tree itself, so the nodes containing it are always opened
+    /// and leaves it shares at [MAX_DEPTH] count without it.
+    pub fn acceleration(&self, bodies: &[NBody], index: usize, opening_angle: f64) -> DVec3 {
+        let body = &bodies[index];
+        let position = body.position;
+        let mut acceleration = DVec3::ZERO;
+        let mut pending = if self.nodes.is_empty() { vec![] } else { vec![0] };
+        while let Some(node) = pending.pop() {
+            let node = &self.nodes[node];
+            if node.mu == 0.0 || node.body == Some(index) {
+                continue;
+            }
+            let contains_body = body.mu != 0.0 && node.contains(position);
+            let (mu, center_of_mass) = if contains_body && node.children.is_none() {
+                let mu = node.mu - body.mu;
+                (mu, (node.center_of_mass * node.mu - position * body.mu) / mu)
+            } else {
+                (node.mu, node.center_of_mass)
+            };
+            let offset = center_of_mass - position;
+            let distance_squared = offset.length_squared();
+            match node.children {
+                Some(children) if contains_body || (2.0 * node.half_size).powi(2) >= opening_angle * opening_angle * distance_squared => {
+                    pending.extend(children..children + 8);
+                }
+                _ if mu != 0.0 && distance_squared > 0.0 => {
+                    acceleration += offset * (mu / (distance_squared * distance_squared.sqrt()));
+                }
+                _ => {}
+            }
+        }
+        acceleration
+    }
+}
+
+/// Computes the gravitational acceleration of every moving body with a Barnes–Hut octree,
+/// spreading the bodies over the compute task pool.
+pub fn accelerations(bodies: &[NBody], accelerations: &mut [DVec3], opening_angle: f64) {
+    let tree = Octree::build(bodies);
+    let tree = &tree;
+    let task_pool = ComputeTaskPool::get_or_init(TaskPool::default);
+    task_pool.scope(|scope| {
+        for (chunk_index, chunk) in accelerations.chunks_mut(BODIES_PER_TASK).enumerate() {
+            scope.spawn(async move {
+                for (offset, acceleration) in chunk.iter_mut().enumerate() {
+                    let index = chunk_index * BODIES_PER_TASK + offset;
+                    *acceleration = if bodies[index].moves {
+                        tree.acceleration(bodies, index, opening_angle)
+                    } else {
+                        DVec3::ZERO
+                    };
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use crate::solarsystem::nbody;
+    use super::*;
+
+    fn body(position: DVec3, mu: f64) -> NBody {
+        NBody {
+            position,
+            velocity: DVec3::ZERO,
+            mu,
+            moves: true,
+        }
+    }
+
+    /// The root mean square deviation of the Barnes–Hut accelerations from the direct sum, relative
+    /// to the root mean square of the direct accelerations.
+    fn deviation(bodies: &[NBody], opening_angle: f64) -> f64 {
+        let mut direct = vec![DVec3::ZERO; bodies.len()];
+        nbody::direct_accelerations(bodies, &mut direct);
+        let mut approximated = vec![DVec3::ZERO; bodies.len()];
+        accelerations(bodies, &mut approximated, opening_angle);
+        let root_mean_square = |values: &mut dyn Iterator<Item = f64>| (values.sum::<f64>() / bodies.len() as f64).sqrt();
+        let error = root_mean_square(&mut direct.iter().zip(approximated.iter()).map(|(direct, approximated)| direct.distance_squared(*approximated)));
+        error / root_mean_square(&mut direct.iter().map(|acceleration| acceleration.length_squared()))
+    }
+
+    #[test]
+    fn agrees_with_direct_sum_for_random_cloud() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut bodies = (0..300)
+            .map(|_| body(DVec3::new(rng.gen_range(-1e9..1e9), rng.gen_range(-1e9..1e9), rng.gen_range(-1e9..1e9)), rng.gen_range(1e10..1e20)))
+            .collect::<Vec<_>>();
+        // Massless bodies are only attracted.
+        bodies.extend((0..50).map(|_| body(DVec3::new(rng.gen_range(-1e9..1e9), rng.gen_range(-1e9..1e9), rng.gen_range(-1e9..1e9)), 0.0)));
+        assert!(deviation(&bodies, 0.0) < 1e-12);
+        assert!(deviation(&bodies, 0.5) < 0.01);
+        assert!(deviation(&bodies, 1.0) < 0.1);
+    }
+
+    #[test]
+    fn bodies_are_not_attracted_by_themselves() {
+        // With a wide opening angle the root would be taken as one mass, the light body included.
+        let bodies = [body(DVec3::ZERO, 1e20), body(DVec3::new(1e9, 0.0, 0.0), 1e19)];
+        assert!(deviation(&bodies, 2.0) < 1e-12);
+    }
+}
@@ -0,0 +1,274 @@
+use bevy::app::App;
+use bevy::asset::{Asset, AssetApp, AssetLoader, AsyncReadExt, BoxedFuture, LoadContext};
+use bevy::asset::io::Reader;
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::reflect::TypePath;
+use bevy::utils::HashSet;
+use serde::Deserialize;
+use thiserror::Error;
+use crate::orbit::to_world_frame;
+use crate::physics_math::double;
+
+
+#[derive(Default)]
+pub struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_asset::<SolarSystemAsset>()
+            .init_asset_loader::<SolarSystemAssetLoader>()
+        ;
+    }
+}
+
+/// Classical orbital elements of a body relative to its parent.
+///
+/// The reference plane is the XZ plane with +Y pointing north, the reference direction is +X.
+/// Angles are in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SolarSystemOrbitDefinition {
+    /// In meters.
+    pub semi_major_axis: f64,
+    #[serde(default)]
+    pub eccentricity: f64,
+    #[serde(default)]
+    pub inclination: f64,
+    #[serde(default)]
+    pub longitude_of_ascending_node: f64,
+    #[serde(default)]
+    pub argument_of_periapsis: f64,
+    /// The position along the orbit at the start, 0 being periapsis.
+    #[serde(default)]
+    pub mean_anomaly: f64,
+}
+
+impl SolarSystemOrbitDefinition {
+    /// The position and velocity relative to the parent, with `mu` being the gravitational
+    /// parameter of the parent and the body.
+    pub fn state(&self, mu: f64) -> (DVec3, DVec3) {
+        let (position, velocity) = double::state_from_elements(
+            mu,
+            self.semi_major_axis,
+            self.eccentricity,
+            self.inclination.to_radians(),
+            self.longitude_of_ascending_node.to_radians(),
+            self.argument_of_periapsis.to_radians(),
+            double::true_anomaly_from_mean(self.mean_anomaly.to_radians(), self.eccentricity).unwrap_or_else(|error| {
+                warn!("{}", error);
+                error.anomaly
+            }),
+        );
+        (to_world_frame(position), to_world_frame(velocity))
+    }
+}
+
+/// Position and velocity of a body relative to its parent.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SolarSystemStateDefinition {
+    /// In meters.
+    pub position: [f64; 3],
+    /// In meters per second.
+    #[serde(default)]
+    pub velocity: [f64; 3],
+}
+
+/// A `[[body]]` of a solar system file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SolarSystemBodyDefinition {
+    pub name: String,
+    /// In kilograms.
+    pub mass: f64,
+    /// In meters.
+    pub radius: f64,
+    /// The height of the atmosphere above the surface in meters, time warp onto rails is refused
+    /// close to it.
+    pub atmosphere_height: Option<f64>,
+    /// The body this one orbits, which has to be listed before it.
+    pub parent: Option<String>,
+    pub orbit: Option<SolarSystemOrbitDefinition>,
+    pub state: Option<SolarSystemStateDefinition>,
+    /// Whether the body follows its orbit around the parent on rails instead of being integrated.
+    #[serde(default = "default_rails")]
+    pub rails: bool,
+    /// Path of the surface texture, relative to the solar system file.
+    pub texture: Option<String>,
+    /// Base color, each channel from 0 to 1.
+    pub color: Option<[f32; 3]>,
+    #[serde(skip)]
+    pub texture_asset: Option<Handle<Image>>,
+}
+
+fn default_rails() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SolarSystemFile {
+    #[serde(rename = "body")]
+    bodies: Vec<SolarSystemBodyDefinition>,
+}
+
+/// A solar system, read from a `.system.toml` file. Bodies are ordered so parents come before
+/// their children.
+#[derive(Asset, TypePath, Debug)]
+pub struct SolarSystemAsset {
+    pub bodies: Vec<SolarSystemBodyDefinition>,
+}
+
+impl SolarSystemAsset {
+    pub fn parent_index(&self, body: &SolarSystemBodyDefinition) -> Option<usize> {
+        let parent = body.parent.as_ref()?;
+        self.bodies.iter().position(|other| other.name == *parent)
+    }
+
+    /// The absolute position and velocity of every body, in the order of [SolarSystemAsset::bodies].
+    pub fn initial_states(&self) -> Vec<(DVec3, DVec3)> {
+        let mut states: Vec<(DVec3, DVec3)> = Vec::with_capacity(self.bodies.len());
+        for body in self.bodies.iter() {
+            let (parent_position, parent_velocity, parent_mass) = match self.parent_index(body) {
+                Some(parent) => (states[parent].0, states[parent].1, self.bodies[parent].mass),
+                None => (DVec3::ZERO, DVec3::ZERO, 0.0),
+            };
+            let (position, velocity) = match (&body.orbit, &body.state) {
+                (Some(orbit), _) => orbit.state(double::gravitational_parameter(parent_mass + body.mass)),
+                (None, Some(state)) => (DVec3::from_array(state.position), DVec3::from_array(state.velocity)),
+                (None, None) => (DVec3::ZERO, DVec3::ZERO),
+            };
+            states.push((parent_position + position, parent_velocity + velocity));
+        }
+        states
+    }
+
+    fn validate(bodies: &[SolarSystemBodyDefinition]) -> Result<(), SolarSystemAssetLoaderError> {
+        if bodies.is_empty() {
+            return Err(SolarSystemAssetLoaderError::NoBodies);
+        }
+        let mut names = HashSet::new();
+        for body in bodies {
+            let invalid = |key: &str| SolarSystemAssetLoaderError::InvalidValue {
+                body: body.name.clone(),
+                key: key.into(),
+            };
+            if body.name.is_empty() {
+                return Err(SolarSystemAssetLoaderError::UnnamedBody);
+            }
+            if !(body.mass.is_finite() && body.mass > 0.0) {
+                return Err(invalid("mass"));
+            }
+            if !(body.radius.is_finite() && body.radius > 0.0) {
+                return Err(invalid("radius"));
+            }
+            if body.atmosphere_height.is_some_and(|height| !(height.is_finite() && height >= 0.0)) {
+                return Err(invalid("atmosphere_height"));
+            }
+            if let Some(color) = body.color {
+                if !color.iter().all(|channel| (0.0..=1.0).contains(channel)) {
+                    return Err(invalid("color"));
+                }
+            }
+            match &body.parent {
+                Some(parent) if !names.contains(parent) => return Err(SolarSystemAssetLoaderError::UnknownParent {
+                    body: body.name.clone(),
+                    parent: parent.clone(),
+                }),
+                Some(_) if body.orbit.is_some() == body.state.is_some() => return Err(SolarSystemAssetLoaderError::AmbiguousOrbit(body.name.clone())),
+                None if body.orbit.is_some() => return Err(invalid("orbit")),
+                _ => {}
+            }
+            if let Some(orbit) = &body.orbit {
+                if !(orbit.semi_major_axis.is_finite() && orbit.semi_major_axis > 0.0) {
+                    return Err(invalid("semi_major_axis"));
+                }
+                if !(0.0..1.0).contains(&orbit.eccentricity) {
+                    return Err(invalid("eccentricity"));
+                }
+                let angles = [orbit.inclination, orbit.longitude_of_ascending_node, orbit.argument_of_periapsis, orbit.mean_anomaly];
+                if !angles.iter().all(|angle| angle.is_finite()) {
+                    return Err(invalid("orbit"));
+                }
+            }
+            if let Some(state) = &body.state {
+                if !state.position.iter().chain(state.velocity.iter()).all(|value| value.is_finite()) {
+                    return Err(invalid("state"));
+                }
+            }
+            if !names.insert(body.name.clone()) {
+                return Err(SolarSystemAssetLoaderError::DuplicateBody(body.name.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum SolarSystemAssetLoaderError {
+    /// An [IO](std::io) Error
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not load asset: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Invalid characters found in solar system")]
+    InvalidCharactersFound,
+    #[error("The solar system has no bodies")]
+    NoBodies,
+    #[error("A body has no name")]
+    UnnamedBody,
+    #[error("Body '{0}' is listed more than once")]
+    DuplicateBody(String),
+    #[error("Body '{body}' orbits '{parent}', which is not listed before it")]
+    UnknownParent { body: String, parent: String },
+    #[error("Body '{0}' needs either an orbit or a state")]
+    AmbiguousOrbit(String),
+    #[error("Invalid value for '{key}' of body '{body}'")]
+    InvalidValue { body: String, key: String },
+}
+
+
+#[derive(Default)]
+pub struct SolarSystemAssetLoader;
+
+impl AssetLoader for SolarSystemAssetLoader {
+    type Asset = SolarSystemAsset;
+    type Settings = ();
+    type Error = SolarSystemAssetLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let (text, _, has_malformed_characters) = encoding_rs::UTF_8.decode(bytes.as_slice());
+            if has_malformed_characters {
+                return Err(SolarSystemAssetLoaderError::InvalidCharactersFound);
+            }
+
+            let file: SolarSystemFile = toml::from_str(&text)?;
+            let mut bodies = file.bodies;
+            SolarSystemAsset::validate(&bodies)?;
+
+            let base_path = load_context.path().parent().map(|path| path.to_path_buf()).unwrap_or_default();
+            for body in bodies.iter_mut() {
+                if let Some(texture) = &body.texture {
+                    body.texture_asset = Some(load_context.load(base_path.join(texture)));
+                }
+            }
+
+            Ok(SolarSystemAsset { bodies })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["system.toml"]
+    }
+}
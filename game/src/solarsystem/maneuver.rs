@@ -0,0 +1,564 @@
+use std::f64::consts::{PI, TAU};
+use bevy::math::{DQuat, DVec3};
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::{LinearVelocity, Rotation};
+use big_space::{FloatingOrigin, FloatingOriginSettings, GridCell};
+use crate::bevy_stupid::dvec3_to_vec3;
+use crate::gentity::gltf::pp_thruster::{allocate_thruster_throttle, read_pilot_input, GEntityFlightInput, GEntityThruster};
+use crate::gentity::script::runtime::GEntityNotification;
+use crate::orbit::{Orbit, Transfer};
+use crate::physics_math::double;
+use crate::solarsystem::{Mass, Name, SimulationPosition, SimulationTime, Velocity};
+use crate::solarsystem::soi::SphereOfInfluence;
+use crate::solarsystem::time_warp::TimeWarp;
+use crate::solarsystem::trajectory::{absolute_position, format_duration, predict_trajectories, sample_conic, TrajectoryPrediction, TrajectorySettings};
+use crate::spaceship::Spaceship;
+
+#[derive(Default)]
+pub struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ManeuverSettings>()
+            .init_resource::<ManeuverTarget>()
+            .add_systems(Startup, setup_maneuver_text)
+            .add_systems(Update, (
+                plan_transfers,
+                edit_maneuver_nodes,
+                plan_maneuvers,
+            ).chain().after(predict_trajectories))
+            .add_systems(Update, execute_maneuvers
+                .after(plan_maneuvers)
+                .after(read_pilot_input)
+                .before(allocate_thruster_throttle))
+            .add_systems(PostUpdate, (draw_maneuver_nodes, update_maneuver_text))
+        ;
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct ManeuverSettings {
+    /// How fast holding a key changes the delta-v of a node, in meters per second per real second.
+    pub delta_v_rate: f64,
+    /// The factor holding Left Control applies to `delta_v_rate`.
+    pub fine_factor: f64,
+    /// How fast holding a key moves a node along its orbit, in periods per real second.
+    pub node_move_rate: f64,
+    /// How fast holding a key moves a node along an open orbit, in seconds per real second.
+    pub open_node_move_rate: f64,
+    /// The remaining delta-v in meters per second below which the autopilot finishes a burn.
+    pub tolerance: f64,
+    /// How far out bi-elliptic transfers reach, relative to the larger radius of both orbits. The
+    /// apoapsis is kept within the sphere of influence of the parent.
+    pub bi_elliptic_apoapsis_factor: f64,
+}
+
+impl Default for ManeuverSettings {
+    fn default() -> Self {
+        Self {
+            delta_v_rate: 10.0,
+            fine_factor: 0.1,
+            node_move_rate: 1.0 / 36.0,
+            open_node_move_rate: 600.0,
+            tolerance: 0.1,
+            bi_elliptic_apoapsis_factor: 40.0,
+        }
+    }
+}
+
+/// A planned impulsive maneuver of a ship, given in the orbital frame at the node.
+///
+/// The orbit the node lies on follows the [TrajectoryPrediction] of the ship until the autopilot
+/// starts executing the node, then both orbits are kept.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct ManeuverNode {
+    /// The simulation time of the maneuver in seconds.
+    pub time: f64,
+    /// In meters per second.
+    pub prograde: f64,
+    /// In meters per second.
+    pub normal: f64,
+    /// In meters per second.
+    pub radial: f64,
+    /// The orbit before the maneuver.
+    pub orbit: Option<Orbit>,
+    /// The orbit after the maneuver, previewed as a trajectory.
+    pub result: Option<Orbit>,
+    /// Whether the autopilot executes the node.
+    pub executing: bool,
+}
+
+impl ManeuverNode {
+    pub fn new(time: f64) -> Self {
+        Self {
+            time,
+            prograde: 0.0,
+            normal: 0.0,
+            radial: 0.0,
+            orbit: None,
+            result: None,
+            executing: false,
+        }
+    }
+
+    /// Gets the velocity change of the node in the game frame, `None` without an orbit.
+    pub fn delta_v(&self) -> Option<DVec3> {
+        self.orbit.map(|orbit| orbit.maneuver_delta_v(self.time, self.prograde, self.normal, self.radial))
+    }
+}
+
+/// The body transfers are planned to, see [plan_transfers].
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ManeuverTarget(pub Option<Entity>);
+
+/// The radius ratio of two orbits above which a bi-elliptic transfer can need less delta-v than a
+/// Hohmann transfer between them.
+const BI_ELLIPTIC_RATIO: f64 = 11.94;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferKind {
+    Hohmann,
+    BiElliptic,
+}
+
+#[derive(Component)]
+pub struct ManeuverText;
+
+/// Gets the first simulation time from `time` on at which the orbit passes its periapsis, or
+/// `time` itself if an open orbit has already passed it.
+fn next_periapsis(orbit: &Orbit, time: f64) -> f64 {
+    let mean_anomaly = orbit.mean_anomaly_at(time);
+    let mean_motion = double::mean_motion(orbit.mu, orbit.semi_major_axis);
+    if orbit.is_closed() {
+        time + (TAU - mean_anomaly) / mean_motion
+    } else {
+        time + (-mean_anomaly).max(0.0) / mean_motion
+    }
+}
+
+/// Plans the first burn of a transfer from the orbit `from` onto the orbit of `target` after `time`.
+/// Both orbit the same parent and are treated as circular and coplanar.
+///
+/// Hohmann transfers start when the target will arrive at the opposite side of the orbit together
+/// with the ship. Bi-elliptic transfers through `apoapsis` are chosen instead if they need less
+/// delta-v, they take too long to meet the target and start at the next periapsis.
+pub fn plan_transfer(from: &Orbit, target: &Orbit, time: f64, apoapsis: f64) -> Option<(TransferKind, Transfer, ManeuverNode)> {
+    let hohmann = Transfer::hohmann(from, target)?;
+    let ratio = from.semi_major_axis.max(target.semi_major_axis) / from.semi_major_axis.min(target.semi_major_axis);
+    let bi_elliptic = Transfer::bi_elliptic(from, target, apoapsis)
+        .filter(|bi_elliptic| ratio > BI_ELLIPTIC_RATIO && bi_elliptic.delta_v() < hohmann.delta_v());
+    if let Some(bi_elliptic) = bi_elliptic {
+        let mut node = ManeuverNode::new(next_periapsis(from, time));
+        node.prograde = bi_elliptic.burns[0];
+        return Some((TransferKind::BiElliptic, bi_elliptic, node));
+    }
+    // How far the target leads the ship, measured in the orbital plane of the ship.
+    let (position, velocity) = from.state_at(time);
+    let target_position = target.state_at(time).0;
+    let normal = position.cross(velocity).normalize();
+    let phase = position.cross(target_position).dot(normal).atan2(position.dot(target_position));
+    let ship_motion = double::mean_motion(from.mu, from.semi_major_axis);
+    let target_motion = double::mean_motion(target.mu, target.semi_major_axis);
+    let relative_motion = target_motion - ship_motion;
+    // The target has to lead by half an orbit, less the way it moves during the transfer.
+    let required_phase = PI - target_motion * hohmann.duration;
+    let wait = if relative_motion != 0.0 {
+        ((required_phase - phase) / relative_motion).rem_euclid(TAU / relative_motion.abs())
+    } else {
+        0.0
+    };
+    let mut node = ManeuverNode::new(time + wait);
+    node.prograde = if target.semi_major_axis > from.semi_major_axis { hohmann.burns[0] } else { -hohmann.burns[0] };
+    Some((TransferKind::Hohmann, hohmann, node))
+}
+
+/// The body whose sphere of influence `entity` is in, the innermost if they nest.
+fn parent_body(
+    entity: Entity,
+    position: DVec3,
+    spheres: &[(Entity, DVec3, f64)],
+) -> Option<Entity> {
+    spheres.iter()
+        .filter(|(body, body_position, radius)| *body != entity && body_position.distance(position) < *radius)
+        .min_by(|(_, _, radius_1), (_, _, radius_2)| radius_1.total_cmp(radius_2))
+        .map(|(body, ..)| *body)
+}
+
+/// Picks the [ManeuverTarget] and plans transfers to it for the player's ship.
+///
+/// T cycles the target through the bodies orbiting the same parent as the ship. H replaces the
+/// maneuver node with the first burn of a transfer to the orbit of the target, see [plan_transfer].
+#[allow(clippy::type_complexity)]
+fn plan_transfers(
+    keyboard_input: Res<Input<KeyCode>>,
+    floating_origin_settings: Res<FloatingOriginSettings>,
+    settings: Res<ManeuverSettings>,
+    simulation_time: Res<SimulationTime>,
+    mut target: ResMut<ManeuverTarget>,
+    ships: Query<(Entity, &TrajectoryPrediction, Option<&ManeuverNode>), With<Spaceship>>,
+    bodies: Query<(Entity, &Name, &Transform, &GridCell<i64>, Option<&SimulationPosition>, Option<&Velocity>, Option<&SphereOfInfluence>, Option<&Orbit>), (With<Mass>, Without<Spaceship>)>,
+    mut notifications: EventWriter<GEntityNotification>,
+    mut cmds: Commands,
+) {
+    let cycle_target = keyboard_input.just_pressed(KeyCode::T);
+    let plan = keyboard_input.just_pressed(KeyCode::H);
+    if !cycle_target && !plan {
+        return;
+    }
+    let Ok((ship, prediction, node)) = ships.get_single() else {
+        return;
+    };
+    let Some(from) = prediction.orbit else {
+        notifications.send(GEntityNotification { entity: ship, text: "Transfers need an orbit".into() });
+        return;
+    };
+    let position_of = |transform: &Transform, grid_cell: &GridCell<i64>, simulation_position: Option<&SimulationPosition>| absolute_position(&floating_origin_settings, transform, grid_cell, simulation_position);
+    let spheres = bodies.iter()
+        .filter_map(|(body, _, transform, grid_cell, simulation_position, _, soi, _)| {
+            soi.map(|soi| (body, position_of(transform, grid_cell, simulation_position), soi.radius))
+        })
+        .collect::<Vec<_>>();
+    if cycle_target {
+        let mut candidates = bodies.iter()
+            .filter_map(|(body, name, transform, grid_cell, simulation_position, ..)| {
+                let parent = parent_body(body, position_of(transform, grid_cell, simulation_position), &spheres);
+                (parent == Some(from.parent)).then(|| (name.0.clone(), body))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort();
+        let next = candidates.iter()
+            .position(|(_, body)| Some(*body) == target.0)
+            .map_or(0, |index| index + 1);
+        target.0 = candidates.get(next).map(|(_, body)| *body);
+        let text = match candidates.get(next) {
+            Some((name, _)) => format!("Target {}", name),
+            None => "No target".into(),
+        };
+        notifications.send(GEntityNotification { entity: ship, text });
+    }
+    if !plan {
+        return;
+    }
+    if node.is_some_and(|node| node.executing) {
+        return;
+    }
+    let Some((_, name, transform, grid_cell, simulation_position, velocity, _, orbit)) = target.0.and_then(|target| bodies.get(target).ok()) else {
+        notifications.send(GEntityNotification { entity: ship, text: "Pick a transfer target with T first".into() });
+        return;
+    };
+    let Ok((_, _, parent_transform, parent_grid_cell, parent_simulation_position, parent_velocity, parent_soi, _)) = bodies.get(from.parent) else {
+        return;
+    };
+    let target_orbit = match orbit {
+        Some(orbit) if orbit.parent == from.parent => Some(*orbit),
+        _ => Orbit::from_state(
+            from.parent,
+            from.mu,
+            position_of(transform, grid_cell, simulation_position) - position_of(parent_transform, parent_grid_cell, parent_simulation_position),
+            velocity.map_or(DVec3::ZERO, |velocity| velocity.velocity) - parent_velocity.map_or(DVec3::ZERO, |velocity| velocity.velocity),
+            simulation_time.0,
+        ),
+    };
+    let apoapsis = (from.semi_major_axis.max(target_orbit.map_or(0.0, |orbit| orbit.semi_major_axis)) * settings.bi_elliptic_apoapsis_factor)
+        .min(parent_soi.map_or(f64::INFINITY, |soi| soi.radius));
+    let Some((kind, transfer, new_node)) = target_orbit.and_then(|target_orbit| plan_transfer(&from, &target_orbit, simulation_time.0, apoapsis)) else {
+        notifications.send(GEntityNotification { entity: ship, text: format!("No transfer to {}, both orbits have to be closed", name.0) });
+        return;
+    };
+    let kind = match kind {
+        TransferKind::Hohmann => "Hohmann",
+        TransferKind::BiElliptic => "Bi-elliptic",
+    };
+    notifications.send(GEntityNotification {
+        entity: ship,
+        text: format!(
+            "{} transfer to {}: {:.1} m/s in {} burns over {}",
+            kind,
+            name.0,
+            transfer.delta_v(),
+            transfer.burns.len(),
+            format_duration(transfer.duration),
+        ),
+    });
+    cmds.entity(ship).insert(new_node);
+}
+
+/// Edits the maneuver node of the player's ship.
+///
+/// N places a node at the next periapsis, Delete removes it and B lets the autopilot execute it.
+/// Page Up/Page Down move the node along the orbit. Numpad 8/2 change the prograde, Numpad 9/3
+/// the normal and Numpad 6/4 the radial delta-v, finer while holding Left Control.
+fn edit_maneuver_nodes(
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    settings: Res<ManeuverSettings>,
+    simulation_time: Res<SimulationTime>,
+    mut ships: Query<(Entity, &TrajectoryPrediction, Option<&mut ManeuverNode>), With<Spaceship>>,
+    mut cmds: Commands,
+) {
+    let axis = |positive: KeyCode, negative: KeyCode| {
+        keyboard_input.pressed(positive) as i8 as f64 - keyboard_input.pressed(negative) as i8 as f64
+    };
+    let fine = if keyboard_input.pressed(KeyCode::ControlLeft) { settings.fine_factor } else { 1.0 };
+    let delta_v_step = settings.delta_v_rate * fine * time.delta_seconds_f64();
+    for (entity, prediction, node) in ships.iter_mut() {
+        let Some(mut node) = node else {
+            if keyboard_input.just_pressed(KeyCode::N) {
+                if let Some(orbit) = prediction.orbit {
+                    cmds.entity(entity).insert(ManeuverNode::new(next_periapsis(&orbit, simulation_time.0)));
+                }
+            }
+            continue;
+        };
+        if keyboard_input.just_pressed(KeyCode::Delete) {
+            cmds.entity(entity).remove::<ManeuverNode>();
+            continue;
+        }
+        if keyboard_input.just_pressed(KeyCode::B) {
+            node.executing = !node.executing;
+        }
+        if node.executing {
+            continue;
+        }
+        let move_rate = match node.orbit.and_then(|orbit| orbit.period()) {
+            Some(period) => period * settings.node_move_rate,
+            None => settings.open_node_move_rate,
+        };
+        let node_time = (node.time + axis(KeyCode::PageUp, KeyCode::PageDown) * move_rate * fine * time.delta_seconds_f64()).max(simulation_time.0);
+        let prograde = node.prograde + axis(KeyCode::Numpad8, KeyCode::Numpad2) * delta_v_step;
+        let normal = node.normal + axis(KeyCode::Numpad9, KeyCode::Numpad3) * delta_v_step;
+        let radial = node.radial + axis(KeyCode::Numpad6, KeyCode::Numpad4) * delta_v_step;
+        if (node_time, prograde, normal, radial) != (node.time, node.prograde, node.normal, node.radial) {
+            node.time = node_time;
+            node.prograde = prograde;
+            node.normal = normal;
+            node.radial = radial;
+        }
+    }
+}
+
+/// Computes the orbits before and after every maneuver node that is not being executed.
+fn plan_maneuvers(
+    mut nodes: Query<(&TrajectoryPrediction, &mut ManeuverNode)>,
+) {
+    for (prediction, mut node) in nodes.iter_mut() {
+        if node.executing {
+            continue;
+        }
+        let orbit = prediction.orbit;
+        let result = orbit.and_then(|orbit| {
+            orbit.after_impulse(node.time, orbit.maneuver_delta_v(node.time, node.prograde, node.normal, node.radial))
+        });
+        if node.orbit != orbit || node.result != result {
+            node.orbit = orbit;
+            node.result = result;
+        }
+    }
+}
+
+/// Flies executing maneuver nodes by steering the flight input of the ship, centering the burn on
+/// the node. The thrusters translate the ship without turning it, so any thruster pointing along
+/// the remaining delta-v helps. Piloting the ship cancels the execution.
+#[allow(clippy::type_complexity)]
+fn execute_maneuvers(
+    time: Res<Time>,
+    time_warp: Res<TimeWarp>,
+    simulation_time: Res<SimulationTime>,
+    settings: Res<ManeuverSettings>,
+    mut ships: Query<(Entity, &mut ManeuverNode, &mut GEntityFlightInput, &LinearVelocity, &Rotation, &GlobalTransform, Option<&bevy_xpbd_3d::prelude::Mass>)>,
+    thrusters: Query<(&GEntityThruster, &GlobalTransform)>,
+    bodies: Query<Option<&Velocity>>,
+    mut cmds: Commands,
+) {
+    // Physics warp runs the thrusters for longer per frame.
+    let physics_delta = time.delta_seconds_f64() * if time_warp.on_rails() { 1.0 } else { time_warp.rate() };
+    for (entity, mut node, mut flight_input, linear_velocity, rotation, ship_transform, mass) in ships.iter_mut() {
+        if !node.executing {
+            continue;
+        }
+        if *flight_input != GEntityFlightInput::default() {
+            info!("Maneuver execution of {:?} cancelled by the pilot", entity);
+            node.executing = false;
+            continue;
+        }
+        let (Some(result), Some(delta_v), Some(mass)) = (node.result, node.delta_v(), mass) else {
+            warn!("Maneuver node of {:?} cannot be executed without an orbit and a mass", entity);
+            node.executing = false;
+            continue;
+        };
+        let inverse_rotation: DQuat = rotation.0.inverse();
+        // The thrust the ship can make along a direction given in its body frame.
+        let available_thrust = |direction: DVec3| thrusters.iter()
+            .filter(|(thruster, _)| thruster.gentity == entity)
+            .map(|(thruster, thruster_transform)| {
+                let forward = thruster_transform.reparented_to(ship_transform).forward().as_dvec3();
+                thruster.max_thrust * forward.dot(direction).max(0.0)
+            })
+            .sum::<f64>();
+        let burn_thrust = available_thrust((inverse_rotation * delta_v).normalize_or_zero());
+        if burn_thrust <= 0.0 {
+            warn!("Maneuver node of {:?} cannot be executed, no thruster points along the burn", entity);
+            node.executing = false;
+            continue;
+        }
+        let burn_duration = delta_v.length() * mass.0 / burn_thrust;
+        if simulation_time.0 < node.time - burn_duration / 2.0 {
+            continue;
+        }
+        let parent_velocity = bodies.get(result.parent).ok().flatten().map_or(DVec3::ZERO, |velocity| velocity.velocity);
+        let remaining = parent_velocity + result.state_at(simulation_time.0).1 - linear_velocity.0;
+        // Stop once the remaining velocity change points against the burn, rather than turning around.
+        if remaining.length() < settings.tolerance || remaining.dot(delta_v) <= 0.0 {
+            info!("Maneuver of {:?} executed, {:.2} m/s remaining", entity, remaining.length());
+            cmds.entity(entity).remove::<ManeuverNode>();
+            continue;
+        }
+        let direction = (inverse_rotation * remaining).normalize();
+        let acceleration = available_thrust(direction) / mass.0;
+        let throttle = if acceleration > 0.0 {
+            (remaining.length() / (acceleration * physics_delta)).min(1.0)
+        } else {
+            1.0
+        };
+        flight_input.translation = direction * throttle;
+    }
+}
+
+/// Draws the maneuver nodes and the trajectories after them relative to the camera.
+fn draw_maneuver_nodes(
+    floating_origin_settings: Res<FloatingOriginSettings>,
+    trajectory_settings: Res<TrajectorySettings>,
+    mut gizmos: Gizmos,
+    cameras: Query<(&Transform, &GridCell<i64>, &GlobalTransform), With<FloatingOrigin>>,
+    bodies: Query<(&Transform, &GridCell<i64>, Option<&SimulationPosition>, Option<&SphereOfInfluence>)>,
+    nodes: Query<&ManeuverNode>,
+) {
+    let Ok((camera_transform, camera_grid_cell, camera_global_transform)) = cameras.get_single() else {
+        return;
+    };
+    let camera_position = floating_origin_settings.grid_position_double::<i64>(camera_grid_cell, camera_transform);
+    let camera_translation = camera_global_transform.translation();
+    for node in nodes.iter() {
+        let (Some(orbit), Some(delta_v)) = (node.orbit, node.delta_v()) else {
+            continue;
+        };
+        let Ok((transform, grid_cell, simulation_position, soi)) = bodies.get(orbit.parent) else {
+            continue;
+        };
+        let offset = absolute_position(&floating_origin_settings, transform, grid_cell, simulation_position) - camera_position;
+        let to_render = |position: DVec3| dvec3_to_vec3(offset + position) + camera_translation;
+        let node_position = orbit.state_at(node.time).0;
+        // Keep the marker the same size on screen.
+        let marker_size = (offset + node_position).length() * 0.01;
+        gizmos.sphere(to_render(node_position), Quat::IDENTITY, marker_size as f32, Color::ORANGE);
+        gizmos.line(
+            to_render(node_position),
+            to_render(node_position + delta_v.normalize_or_zero() * marker_size * 5.0),
+            Color::ORANGE_RED,
+        );
+        if let Some(result) = node.result {
+            let soi_radius = soi.map_or(f64::INFINITY, |soi| soi.radius);
+            let points = sample_conic(&result, result.true_anomaly_at(node.time), soi_radius, trajectory_settings.samples.max(1));
+            gizmos.linestrip(points.into_iter().map(to_render), Color::ORANGE);
+        }
+    }
+}
+
+fn setup_maneuver_text(mut cmds: Commands) {
+    cmds.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::ORANGE,
+                ..default()
+            },
+        )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(10.0),
+                right: Val::Px(10.0),
+                ..default()
+            }),
+        ManeuverText,
+    ));
+}
+
+fn update_maneuver_text(
+    simulation_time: Res<SimulationTime>,
+    nodes: Query<&ManeuverNode, With<Spaceship>>,
+    mut texts: Query<&mut Text, With<ManeuverText>>,
+) {
+    let value = match nodes.get_single() {
+        Ok(node) => format!(
+            "Node in {}{}\nPrograde {:.1} m/s, normal {:.1} m/s, radial {:.1} m/s\nDelta-v {:.1} m/s{}",
+            if node.time < simulation_time.0 { "-" } else { "" },
+            format_duration((node.time - simulation_time.0).abs()),
+            node.prograde,
+            node.normal,
+            node.radial,
+            node.delta_v().map_or(0.0, |delta_v| delta_v.length()),
+            if node.executing { "\nExecuting" } else { "" },
+        ),
+        Err(_) => String::new(),
+    };
+    for mut text in texts.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EARTH_MU: f64 = 3.986004418e14;
+
+    fn circular_orbit(radius: f64, mean_anomaly_at_epoch: f64) -> Orbit {
+        Orbit {
+            parent: Entity::PLACEHOLDER,
+            mu: EARTH_MU,
+            semi_major_axis: radius,
+            eccentricity: 0.0,
+            inclination: 0.0,
+            longitude_of_ascending_node: 0.0,
+            argument_of_periapsis: 0.0,
+            mean_anomaly_at_epoch,
+            epoch: 0.0,
+        }
+    }
+
+    #[test]
+    fn hohmann_transfers_meet_the_target() {
+        for (from, target) in [
+            (circular_orbit(6678e3, 0.0), circular_orbit(42164e3, 2.0)),
+            (circular_orbit(42164e3, 0.0), circular_orbit(6678e3, -1.0)),
+            (circular_orbit(7e6, 1.0), circular_orbit(8e6, 0.3)),
+        ] {
+            let (kind, transfer, node) = plan_transfer(&from, &target, 100.0, 40.0 * target.semi_major_axis).unwrap();
+            assert_eq!(kind, TransferKind::Hohmann);
+            assert!(node.time >= 100.0);
+            let delta_v = from.maneuver_delta_v(node.time, node.prograde, node.normal, node.radial);
+            let result = from.after_impulse(node.time, delta_v).unwrap();
+            let arrival = node.time + transfer.duration;
+            let miss = result.state_at(arrival).0.distance(target.state_at(arrival).0);
+            assert!(miss < 1e-3 * target.semi_major_axis, "{:?} to {:?} misses by {} m", from, target, miss);
+        }
+    }
+
+    #[test]
+    fn bi_elliptic_transfers_are_planned_for_distant_targets() {
+        let from = circular_orbit(7e6, 0.0);
+        let target = circular_orbit(20.0 * 7e6, 0.0);
+        let (kind, transfer, node) = plan_transfer(&from, &target, 100.0, 40.0 * target.semi_major_axis).unwrap();
+        assert_eq!(kind, TransferKind::BiElliptic);
+        assert_eq!(transfer.burns.len(), 3);
+        assert_eq!(node.prograde, transfer.burns[0]);
+        let (kind, ..) = plan_transfer(&from, &circular_orbit(11.0 * 7e6, 0.0), 100.0, 40.0 * 11.0 * 7e6).unwrap();
+        assert_eq!(kind, TransferKind::Hohmann);
+        let escape = Orbit { eccentricity: 1.5, ..circular_orbit(-7e7, 0.0) };
+        assert!(plan_transfer(&from, &escape, 100.0, 7e8).is_none());
+    }
+}
@@ -0,0 +1,148 @@
+use bevy::math::DVec3;
+#[cfg(test)]
+use crate::physics_math::double;
+
+/// A body of the n-body simulation, in absolute coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NBody {
+    /// In meters.
+    pub position: DVec3,
+    /// In meters per second.
+    pub velocity: DVec3,
+    /// The gravitational parameter, 0 for bodies that do not attract others.
+    pub mu: f64,
+    /// Whether the integrator moves the body. Fixed bodies, eg. the ones on rails, only attract.
+    pub moves: bool,
+}
+
+/// Computes the gravitational acceleration of every moving body by summing over all other bodies.
+pub fn direct_accelerations(bodies: &[NBody], accelerations: &mut [DVec3]) {
+    for (index, (body, acceleration)) in bodies.iter().zip(accelerations.iter_mut()).enumerate() {
+        *acceleration = DVec3::ZERO;
+        if !body.moves {
+            continue;
+        }
+        for (other_index, other) in bodies.iter().enumerate() {
+            if index == other_index || other.mu == 0.0 {
+                continue;
+            }
+            let offset = other.position - body.position;
+            let distance_squared = offset.length_squared();
+            if distance_squared == 0.0 {
+                continue;
+            }
+            *acceleration += offset * (other.mu / (distance_squared * distance_squared.sqrt()));
+        }
+    }
+}
+
+/// Advances the bodies by `dt` seconds with one velocity Verlet step.
+///
+/// `accelerations` has to hold the accelerations at the current positions, afterwards it holds
+/// the ones at the new positions.
+pub fn velocity_verlet_step(bodies: &mut [NBody], accelerations: &mut [DVec3], dt: f64, solver: impl Fn(&[NBody], &mut [DVec3])) {
+    for (body, acceleration) in bodies.iter_mut().zip(accelerations.iter()) {
+        if body.moves {
+            body.velocity += *acceleration * (dt / 2.0);
+            body.position += body.velocity * dt;
+        }
+    }
+    solver(bodies, accelerations);
+    for (body, acceleration) in bodies.iter_mut().zip(accelerations.iter()) {
+        if body.moves {
+            body.velocity += *acceleration * (dt / 2.0);
+        }
+    }
+}
+
+/// Advances the bodies by `duration` seconds in equal steps of at most `max_dt` seconds.
+///
+/// If that takes more than `max_substeps` steps, the steps get longer instead.
+/// Returns the number of steps taken.
+pub fn integrate(bodies: &mut [NBody], duration: f64, max_dt: f64, max_substeps: usize, solver: impl Fn(&[NBody], &mut [DVec3])) -> usize {
+    if duration <= 0.0 || bodies.is_empty() {
+        return 0;
+    }
+    let substeps = ((duration / max_dt).ceil() as usize).clamp(1, max_substeps.max(1));
+    let dt = duration / substeps as f64;
+    let mut accelerations = vec![DVec3::ZERO; bodies.len()];
+    solver(bodies, &mut accelerations);
+    for _ in 0..substeps {
+        velocity_verlet_step(bodies, &mut accelerations, dt, &solver);
+    }
+    substeps
+}
+
+#[cfg(test)]
+const SUN_MASS: f64 = 1.989e30;
+#[cfg(test)]
+const EARTH_MASS: f64 = 5.9722e24;
+#[cfg(test)]
+const EARTH_DISTANCE: f64 = 149.598e9;
+#[cfg(test)]
+const DAY: f64 = 86400.0;
+
+/// Integrates like the game does at one simulated day per frame, with hourly substeps.
+#[cfg(test)]
+fn integrate_days(bodies: &mut [NBody], days: f64) {
+    let mut remaining = days * DAY;
+    while remaining > 0.0 {
+        let duration = remaining.min(DAY);
+        integrate(bodies, duration, 3600.0, 1000, direct_accelerations);
+        remaining -= duration;
+    }
+}
+
+#[test]
+fn earth_orbits_a_fixed_sun_in_a_year() {
+    let sun_mu = double::gravitational_parameter(SUN_MASS);
+    let start = DVec3::new(EARTH_DISTANCE, 0.0, 0.0);
+    let mut bodies = [
+        NBody { position: DVec3::ZERO, velocity: DVec3::ZERO, mu: sun_mu, moves: false },
+        NBody {
+            position: start,
+            velocity: DVec3::new(0.0, 0.0, -double::circular_velocity(sun_mu, EARTH_DISTANCE)),
+            mu: double::gravitational_parameter(EARTH_MASS),
+            moves: true,
+        },
+    ];
+    let period_days = double::orbital_period(sun_mu, EARTH_DISTANCE) / DAY;
+    assert!((period_days - 365.25).abs() < 0.5, "period is {} days", period_days);
+
+    integrate_days(&mut bodies, period_days / 2.0);
+    assert!(bodies[1].position.distance(-start) < EARTH_DISTANCE * 1e-3, "half an orbit ends at {}", bodies[1].position);
+
+    integrate_days(&mut bodies, period_days / 2.0);
+    // One day of orbit is about 1.7% of the distance to the sun, the error has to stay far below.
+    assert!(bodies[1].position.distance(start) < EARTH_DISTANCE * 1e-3, "a full orbit ends at {}", bodies[1].position);
+    assert!((bodies[1].position.length() / EARTH_DISTANCE - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn earth_and_sun_orbit_their_barycenter_in_a_year() {
+    let sun_mu = double::gravitational_parameter(SUN_MASS);
+    let earth_mu = double::gravitational_parameter(EARTH_MASS);
+    let speed = double::circular_velocity(sun_mu + earth_mu, EARTH_DISTANCE);
+    // Both bodies start with opposite momenta, so the barycenter stays at rest.
+    let mut bodies = [
+        NBody {
+            position: DVec3::ZERO,
+            velocity: DVec3::new(0.0, 0.0, speed * earth_mu / (sun_mu + earth_mu)),
+            mu: sun_mu,
+            moves: true,
+        },
+        NBody {
+            position: DVec3::new(EARTH_DISTANCE, 0.0, 0.0),
+            velocity: DVec3::new(0.0, 0.0, -speed * sun_mu / (sun_mu + earth_mu)),
+            mu: earth_mu,
+            moves: true,
+        },
+    ];
+    let start = bodies[1].position - bodies[0].position;
+    let period_days = double::orbital_period(sun_mu + earth_mu, EARTH_DISTANCE) / DAY;
+    assert!((period_days - 365.25).abs() < 0.5, "period is {} days", period_days);
+
+    integrate_days(&mut bodies, period_days);
+    let relative = bodies[1].position - bodies[0].position;
+    assert!(relative.distance(start) < EARTH_DISTANCE * 1e-3, "a full orbit ends at {}", relative);
+}
@@ -0,0 +1,118 @@
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::{LinearVelocity, Position};
+use big_space::{FloatingOriginSettings, GridCell};
+use crate::gentity::gltf::pp_thruster::GEntityFlightInput;
+use crate::orbit::Orbit;
+use crate::physics_math::double;
+use crate::solarsystem::{Mass, SimulationTime, Velocity};
+use crate::solarsystem::time_warp::OnRails;
+
+/// The region around a celestial body within which it dominates the motion of small objects.
+/// Infinite for the root of the solar system.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct SphereOfInfluence {
+    /// In meters.
+    pub radius: f64,
+}
+
+/// Marks entities whose [InSphereOfInfluence] is tracked.
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct SoiTracked;
+
+/// The body whose sphere of influence a [SoiTracked] entity is in, the innermost if they nest.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InSphereOfInfluence(pub Entity);
+
+/// Lets a [SoiTracked] body follow a conic around the body of its sphere of influence while it is
+/// [OnRails], instead of being moved by the physics.
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct PatchedConics;
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SoiChanged {
+    pub entity: Entity,
+    /// `None` when the sphere of influence is determined for the first time.
+    pub previous: Option<Entity>,
+    pub current: Entity,
+}
+
+pub(crate) fn track_spheres_of_influence(
+    floating_origin_settings: Res<FloatingOriginSettings>,
+    bodies: Query<(Entity, &SphereOfInfluence, &Transform, &GridCell<i64>)>,
+    tracked: Query<(Entity, &Transform, &GridCell<i64>, Option<&InSphereOfInfluence>), With<SoiTracked>>,
+    mut soi_events: EventWriter<SoiChanged>,
+    mut cmds: Commands,
+) {
+    for (entity, transform, grid_cell, current) in tracked.iter() {
+        let position = floating_origin_settings.grid_position_double::<i64>(grid_cell, transform);
+        let innermost = bodies.iter()
+            .filter(|(body, soi, body_transform, body_grid_cell)| {
+                *body != entity && (soi.radius.is_infinite()
+                    || floating_origin_settings.grid_position_double::<i64>(body_grid_cell, body_transform).distance(position) < soi.radius)
+            })
+            .min_by(|(_, soi_1, ..), (_, soi_2, ..)| soi_1.radius.total_cmp(&soi_2.radius))
+            .map(|(body, ..)| body);
+        let Some(body) = innermost else {
+            continue;
+        };
+        if current.map(|current| current.0) == Some(body) {
+            continue;
+        }
+        cmds.entity(entity).insert(InSphereOfInfluence(body));
+        soi_events.send(SoiChanged {
+            entity,
+            previous: current.map(|current| current.0),
+            current: body,
+        });
+    }
+}
+
+/// Moves [PatchedConics] bodies [OnRails] along an [Orbit] around the body of their sphere of
+/// influence. Thrusting or changing the sphere of influence drops the orbit, coasting again
+/// computes a new one from the current state.
+#[allow(clippy::type_complexity)]
+pub(crate) fn propagate_patched_conics(
+    floating_origin_settings: Res<FloatingOriginSettings>,
+    simulation_time: Res<SimulationTime>,
+    bodies: Query<(&Mass, &Transform, &GridCell<i64>, Option<&Velocity>), Without<PatchedConics>>,
+    mut vessels: Query<(Entity, &InSphereOfInfluence, &Transform, &GridCell<i64>, &mut Position, &mut LinearVelocity, Option<&Orbit>, Option<&GEntityFlightInput>), (With<PatchedConics>, With<OnRails>)>,
+    mut cmds: Commands,
+) {
+    for (entity, soi, transform, grid_cell, mut position, mut linear_velocity, orbit, flight_input) in vessels.iter_mut() {
+        let thrusting = flight_input.is_some_and(|flight_input| *flight_input != GEntityFlightInput::default());
+        let Some((mass, body_transform, body_grid_cell, body_velocity)) = bodies.get(soi.0).ok().filter(|_| !thrusting) else {
+            if orbit.is_some() {
+                cmds.entity(entity).remove::<Orbit>();
+            }
+            continue;
+        };
+        let body_position = floating_origin_settings.grid_position_double::<i64>(body_grid_cell, body_transform);
+        let body_velocity = body_velocity.map_or(DVec3::ZERO, |velocity| velocity.velocity);
+        let vessel_position = floating_origin_settings.grid_position_double::<i64>(grid_cell, transform);
+        match orbit {
+            Some(orbit) if orbit.parent == soi.0 => {
+                let (relative_position, relative_velocity) = orbit.state_at(simulation_time.0);
+                position.0 += body_position + relative_position - vessel_position;
+                linear_velocity.0 = body_velocity + relative_velocity;
+            }
+            _ => {
+                let orbit = Orbit::from_state(
+                    soi.0,
+                    double::gravitational_parameter(mass.value()),
+                    vessel_position - body_position,
+                    linear_velocity.0 - body_velocity,
+                    simulation_time.0,
+                );
+                match orbit {
+                    Some(orbit) => {
+                        cmds.entity(entity).insert(orbit);
+                    }
+                    None => {
+                        cmds.entity(entity).remove::<Orbit>();
+                    }
+                }
+            }
+        }
+    }
+}
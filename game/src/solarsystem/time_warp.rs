@@ -0,0 +1,218 @@
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::{Physics, PhysicsTime, RigidBody};
+use big_space::{FloatingOriginSettings, GridCell};
+use crate::gentity::gltf::pp_atmosphere::GEntityAtmosphere;
+use crate::gentity::gltf::pp_gravity::GEntityGravityVolume;
+use crate::gentity::gltf::pp_seat::GEntitySeated;
+use crate::gentity::gltf::pp_thruster::GEntityThruster;
+use crate::gentity::script::runtime::GEntityNotification;
+use crate::gravity::LocalGravity;
+use crate::orbit::Orbit;
+use crate::player::Player;
+use crate::solarsystem::{BodyAtmosphere, Name, SimulationPosition};
+use crate::solarsystem::soi::PatchedConics;
+
+/// How fast the simulated time passes, in discrete levels.
+///
+/// Up to `max_physics_rate`, the physics runs faster too. Above, vessels with [PatchedConics] are
+/// put [OnRails] instead, which needs them to coast clear of atmospheres with nobody and nothing
+/// loose inside, as the conic moves only the vessel.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct TimeWarp {
+    /// The simulated seconds per real second of each level, ascending and starting at 1.
+    pub levels: Vec<f64>,
+    /// The index of the current level.
+    pub level: usize,
+    /// The highest rate the physics is sped up to.
+    pub max_physics_rate: f64,
+    /// How far above the top of an atmosphere vessels have to be to go on rails, in meters.
+    pub atmosphere_clearance: f64,
+}
+
+impl Default for TimeWarp {
+    fn default() -> Self {
+        Self {
+            levels: vec![1.0, 2.0, 3.0, 4.0, 10.0, 100.0, 1_000.0, 10_000.0, 100_000.0, 1_000_000.0],
+            level: 0,
+            max_physics_rate: 4.0,
+            atmosphere_clearance: 10e3,
+        }
+    }
+}
+
+impl TimeWarp {
+    /// Gets the simulated seconds per real second.
+    pub fn rate(&self) -> f64 {
+        self.levels.get(self.level).copied().unwrap_or(1.0)
+    }
+
+    /// Whether vessels are moved on rails instead of by the physics.
+    pub fn on_rails(&self) -> bool {
+        self.rate() > self.max_physics_rate
+    }
+
+    /// Gets the highest level that keeps vessels in the physics.
+    pub fn max_physics_level(&self) -> usize {
+        self.levels.iter().rposition(|rate| *rate <= self.max_physics_rate).unwrap_or(0)
+    }
+}
+
+/// Marks vessels following their patched conic while the time is warped, see [TimeWarp].
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct OnRails;
+
+#[derive(Component)]
+pub struct TimeWarpText;
+
+/// Changes the time warp level.
+///
+/// Period warps faster, Comma slower and Slash returns to real time. Warping onto rails is
+/// refused, and dropped back to physics warp, while a vessel is thrusting, close to an atmosphere
+/// or has an unseated player or a loose dynamic body inside, see [loose_inside].
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub(crate) fn change_time_warp(
+    keyboard_input: Res<Input<KeyCode>>,
+    floating_origin_settings: Res<FloatingOriginSettings>,
+    mut time_warp: ResMut<TimeWarp>,
+    vessels: Query<(Entity, &Transform, &GridCell<i64>), With<PatchedConics>>,
+    thrusters: Query<&GEntityThruster>,
+    atmospheres: Query<(&BodyAtmosphere, &Name, &Transform, &GridCell<i64>, Option<&SimulationPosition>)>,
+    loose_bodies: Query<(Entity, &RigidBody, &GlobalTransform, Option<&LocalGravity>, Has<Player>), (Without<GEntitySeated>, Without<PatchedConics>)>,
+    gravity_volumes: Query<&GEntityGravityVolume>,
+    air_volumes: Query<(&GEntityAtmosphere, &GlobalTransform)>,
+    parents: Query<&Parent>,
+    mut notifications: EventWriter<GEntityNotification>,
+) {
+    let last_level = time_warp.levels.len().saturating_sub(1);
+    let mut level = time_warp.level.min(last_level);
+    if keyboard_input.just_pressed(KeyCode::Period) {
+        level = (level + 1).min(last_level);
+    }
+    if keyboard_input.just_pressed(KeyCode::Comma) {
+        level = level.saturating_sub(1);
+    }
+    if keyboard_input.just_pressed(KeyCode::Slash) {
+        level = 0;
+    }
+    let max_physics_level = time_warp.max_physics_level();
+    if level > max_physics_level {
+        let restriction = vessels.iter().find_map(|(vessel, transform, grid_cell)| {
+            if thrusters.iter().any(|thruster| thruster.gentity == vessel && thruster.throttle > 0.0) {
+                return Some((vessel, "while thrusting".to_string()));
+            }
+            let loose = loose_bodies.iter()
+                .filter(|(_, rigid_body, .., is_player)| rigid_body.is_dynamic() || *is_player)
+                .find(|(body, _, transform, local_gravity, _)| {
+                    loose_inside(vessel, *body, transform, *local_gravity, &gravity_volumes, &air_volumes, &parents)
+                });
+            if let Some((.., is_player)) = loose {
+                let reason = if is_player { "while a player is not seated" } else { "while something is loose inside" };
+                return Some((vessel, reason.to_string()));
+            }
+            let position = floating_origin_settings.grid_position_double::<i64>(grid_cell, transform);
+            atmospheres.iter()
+                .find(|(atmosphere, _, transform, grid_cell, simulation_position)| {
+                    let body_position = simulation_position.map_or_else(|| floating_origin_settings.grid_position_double::<i64>(grid_cell, transform), |position| position.0);
+                    position.distance(body_position) < atmosphere.outer_radius + time_warp.atmosphere_clearance
+                })
+                .map(|(_, name, ..)| (vessel, format!("near the atmosphere of {}", name.0)))
+        });
+        if let Some((vessel, reason)) = restriction {
+            let text = if time_warp.on_rails() {
+                format!("Time warp dropped to {}x {}", time_warp.levels[max_physics_level], reason)
+            } else {
+                format!("Cannot warp faster than {}x {}", time_warp.levels[max_physics_level], reason)
+            };
+            if time_warp.level != max_physics_level || level > time_warp.level {
+                notifications.send(GEntityNotification { entity: vessel, text });
+            }
+            level = max_physics_level;
+        }
+    }
+    if time_warp.level != level {
+        time_warp.level = level;
+    }
+}
+
+/// Whether `body` is inside `vessel` without being part of it: pulled by one of the gravity volumes
+/// of the vessel or within one of its air boxes. Such bodies would be left behind when the vessel
+/// goes [OnRails].
+fn loose_inside(
+    vessel: Entity,
+    body: Entity,
+    transform: &GlobalTransform,
+    local_gravity: Option<&LocalGravity>,
+    gravity_volumes: &Query<&GEntityGravityVolume>,
+    air_volumes: &Query<(&GEntityAtmosphere, &GlobalTransform)>,
+    parents: &Query<&Parent>,
+) -> bool {
+    if parents.iter_ancestors(body).any(|ancestor| ancestor == vessel) {
+        return false;
+    }
+    let in_gravity = local_gravity
+        .and_then(|local_gravity| gravity_volumes.get(local_gravity.volume).ok())
+        .is_some_and(|volume| volume.gentity == vessel);
+    in_gravity || air_volumes.iter().any(|(air_volume, air_transform)| {
+        air_volume.gentity == vessel && GEntityAtmosphere::contains(air_transform, transform.translation())
+    })
+}
+
+/// Speeds up the physics up to the physics warp rate and puts vessels on and off rails.
+///
+/// Vessels coming off rails keep the position and velocity their conic gave them last, only their
+/// orbit is dropped so it is computed anew when they go back on rails.
+pub(crate) fn apply_time_warp(
+    time_warp: Res<TimeWarp>,
+    mut physics_time: ResMut<Time<Physics>>,
+    vessels: Query<(Entity, Has<OnRails>), With<PatchedConics>>,
+    mut cmds: Commands,
+) {
+    let timescale = if time_warp.on_rails() { 1.0 } else { time_warp.rate() };
+    if physics_time.relative_speed_f64() != timescale {
+        physics_time.set_relative_speed_f64(timescale);
+    }
+    for (vessel, on_rails) in vessels.iter() {
+        match (time_warp.on_rails(), on_rails) {
+            (true, false) => {
+                cmds.entity(vessel).insert(OnRails);
+            }
+            (false, true) => {
+                cmds.entity(vessel).remove::<(OnRails, Orbit)>();
+            }
+            _ => {}
+        }
+    }
+}
+
+pub(crate) fn setup_time_warp_text(mut cmds: Commands) {
+    cmds.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                right: Val::Px(10.0),
+                ..default()
+            }),
+        TimeWarpText,
+    ));
+}
+
+pub(crate) fn update_time_warp_text(
+    time_warp: Res<TimeWarp>,
+    mut texts: Query<&mut Text, With<TimeWarpText>>,
+) {
+    if !time_warp.is_changed() {
+        return;
+    }
+    let value = format!("Warp {}x{}", time_warp.rate(), if time_warp.on_rails() { " (on rails)" } else { "" });
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
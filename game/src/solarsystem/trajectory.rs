@@ -0,0 +1,344 @@
+use std::f64::consts::{PI, TAU};
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::LinearVelocity;
+use big_space::{FloatingOrigin, FloatingOriginSettings, GridCell};
+use crate::bevy_stupid::dvec3_to_vec3;
+use crate::orbit::Orbit;
+use crate::physics_math::double;
+use crate::solarsystem::{Mass, MassNoEffect, SimulationPosition, SimulationTime, Velocity};
+use crate::solarsystem::nbody::{self, NBody};
+use crate::solarsystem::soi::{InSphereOfInfluence, SphereOfInfluence};
+
+const ASTRONOMICAL_UNIT: f64 = 149.597_870_7e9;
+
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct TrajectorySettings {
+    /// The number of line segments per trajectory.
+    pub samples: usize,
+    /// How far ahead in seconds open trajectories are integrated numerically.
+    pub horizon: f64,
+    /// The limit in seconds for integrating closed trajectories, which are integrated for one period.
+    pub max_horizon: f64,
+    /// The longest integration step in seconds.
+    pub max_dt: f64,
+    /// The most integration steps per line segment.
+    pub max_substeps: usize,
+    /// How often in real seconds numerically integrated trajectories are recomputed.
+    pub refresh_interval: f64,
+}
+
+impl Default for TrajectorySettings {
+    fn default() -> Self {
+        Self {
+            samples: 256,
+            horizon: 30.0 * 86400.0,
+            max_horizon: 2.0 * 365.25 * 86400.0,
+            max_dt: 3600.0,
+            max_substeps: 64,
+            refresh_interval: 0.5,
+        }
+    }
+}
+
+/// The predicted trajectory of an entity, drawn as a gizmo line with labels for its apsides and
+/// period.
+///
+/// Entities with an [Orbit] follow its conic, all others are integrated numerically under the
+/// gravity of all bodies without [MassNoEffect].
+#[derive(Component, Debug, Clone)]
+pub struct TrajectoryPrediction {
+    pub color: Color,
+    /// The body the trajectory is relative to, the parent of the orbit or the body of the sphere
+    /// of influence.
+    pub reference: Option<Entity>,
+    /// Positions along the trajectory in meters, relative to `reference`.
+    pub points: Vec<DVec3>,
+    /// The orbit the labels are taken from, osculating for numerically integrated trajectories.
+    pub orbit: Option<Orbit>,
+    numeric: bool,
+}
+
+impl TrajectoryPrediction {
+    pub fn new(color: Color) -> Self {
+        Self {
+            color,
+            reference: None,
+            points: vec![],
+            orbit: None,
+            numeric: false,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.reference = None;
+        self.points.clear();
+        self.orbit = None;
+        self.numeric = false;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrajectoryLabelKind {
+    Periapsis,
+    Apoapsis,
+    Period,
+}
+
+/// A UI text following a point of the [TrajectoryPrediction] of `target` on screen.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct TrajectoryLabel {
+    pub target: Entity,
+    pub kind: TrajectoryLabelKind,
+}
+
+pub(crate) fn absolute_position(
+    floating_origin_settings: &FloatingOriginSettings,
+    transform: &Transform,
+    grid_cell: &GridCell<i64>,
+    simulation_position: Option<&SimulationPosition>,
+) -> DVec3 {
+    simulation_position.map_or_else(|| floating_origin_settings.grid_position_double::<i64>(grid_cell, transform), |position| position.0)
+}
+
+/// Samples a conic relative to its parent. Closed orbits are sampled all around, open ones from
+/// `true_anomaly` until they leave the sphere of influence of the parent.
+pub(crate) fn sample_conic(orbit: &Orbit, true_anomaly: f64, soi_radius: f64, samples: usize) -> Vec<DVec3> {
+    let (start, end) = if orbit.is_closed() {
+        (0.0, TAU)
+    } else {
+        // Stay clear of the asymptotes, where the distance goes to infinity.
+        let limit = (-1.0 / orbit.eccentricity).acos() * 0.99;
+        let semi_latus_rectum = orbit.semi_major_axis * (1.0 - orbit.eccentricity * orbit.eccentricity);
+        let end = if soi_radius.is_finite() {
+            ((semi_latus_rectum / soi_radius - 1.0) / orbit.eccentricity).clamp(-1.0, 1.0).acos().min(limit)
+        } else {
+            limit
+        };
+        (true_anomaly.min(end), end)
+    };
+    (0..=samples)
+        .map(|sample| orbit.state_at_true_anomaly(start + (end - start) * sample as f64 / samples as f64).0)
+        .collect()
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn predict_trajectories(
+    floating_origin_settings: Res<FloatingOriginSettings>,
+    simulation_time: Res<SimulationTime>,
+    settings: Res<TrajectorySettings>,
+    time: Res<Time>,
+    attractors: Query<(Entity, &Mass, &Transform, &GridCell<i64>, Option<&Velocity>, Option<&SimulationPosition>, Option<&SphereOfInfluence>), Without<MassNoEffect>>,
+    mut predictions: Query<(Entity, &Transform, &GridCell<i64>, Option<&Mass>, Option<&Velocity>, Option<&LinearVelocity>, Option<&SimulationPosition>, Option<&Orbit>, Option<&InSphereOfInfluence>, &mut TrajectoryPrediction)>,
+    mut since_refresh: Local<f64>,
+) {
+    *since_refresh += time.delta_seconds_f64();
+    let refresh = *since_refresh >= settings.refresh_interval;
+    if refresh {
+        *since_refresh = 0.0;
+    }
+    let samples = settings.samples.max(1);
+    for (entity, transform, grid_cell, mass, velocity, linear_velocity, simulation_position, orbit, soi, mut prediction) in predictions.iter_mut() {
+        let reference = orbit.map(|orbit| orbit.parent)
+            .or(soi.map(|soi| soi.0))
+            .filter(|reference| *reference != entity);
+        let Some((reference, reference_mass, reference_transform, reference_grid_cell, reference_velocity, reference_simulation_position, reference_soi)) =
+            reference.and_then(|reference| attractors.get(reference).ok()) else {
+            prediction.clear();
+            continue;
+        };
+        match orbit {
+            Some(orbit) if orbit.parent == reference => {
+                let soi_radius = reference_soi.map_or(f64::INFINITY, |soi| soi.radius);
+                prediction.points = sample_conic(orbit, orbit.true_anomaly_at(simulation_time.0), soi_radius, samples);
+                prediction.orbit = Some(*orbit);
+                prediction.reference = Some(reference);
+                prediction.numeric = false;
+            }
+            _ => {
+                if !refresh && prediction.numeric && prediction.reference == Some(reference) {
+                    continue;
+                }
+                let position = absolute_position(&floating_origin_settings, transform, grid_cell, simulation_position);
+                let velocity = velocity.map(|velocity| velocity.velocity)
+                    .or(linear_velocity.map(|velocity| velocity.0))
+                    .unwrap_or(DVec3::ZERO);
+                let reference_position = absolute_position(&floating_origin_settings, reference_transform, reference_grid_cell, reference_simulation_position);
+                let reference_velocity = reference_velocity.map_or(DVec3::ZERO, |velocity| velocity.velocity);
+                let osculating = Orbit::from_state(
+                    reference,
+                    double::gravitational_parameter(reference_mass.value() + mass.map_or(0.0, |mass| mass.value())),
+                    position - reference_position,
+                    velocity - reference_velocity,
+                    simulation_time.0,
+                );
+                let horizon = osculating.and_then(|orbit| orbit.period()).unwrap_or(settings.horizon).min(settings.max_horizon);
+
+                // Everything moves freely during the prediction, including the bodies on rails,
+                // whose orbits stem from the same gravity.
+                let mut bodies = vec![];
+                let mut index = None;
+                let mut reference_index = 0;
+                for (attractor, mass, transform, grid_cell, velocity, simulation_position, _) in attractors.iter() {
+                    if attractor == entity {
+                        index = Some(bodies.len());
+                    }
+                    if attractor == reference {
+                        reference_index = bodies.len();
+                    }
+                    bodies.push(NBody {
+                        position: absolute_position(&floating_origin_settings, transform, grid_cell, simulation_position),
+                        velocity: velocity.map_or(DVec3::ZERO, |velocity| velocity.velocity),
+                        mu: double::gravitational_parameter(mass.value()),
+                        moves: true,
+                    });
+                }
+                let index = index.unwrap_or_else(|| {
+                    bodies.push(NBody { position, velocity, mu: 0.0, moves: true });
+                    bodies.len() - 1
+                });
+                let segment = horizon / samples as f64;
+                let mut points = Vec::with_capacity(samples + 1);
+                points.push(bodies[index].position - bodies[reference_index].position);
+                for _ in 0..samples {
+                    nbody::integrate(&mut bodies, segment, settings.max_dt, settings.max_substeps, nbody::direct_accelerations);
+                    points.push(bodies[index].position - bodies[reference_index].position);
+                }
+                prediction.points = points;
+                prediction.orbit = osculating;
+                prediction.reference = Some(reference);
+                prediction.numeric = true;
+            }
+        }
+    }
+}
+
+/// Draws the trajectories relative to the camera, so they keep their precision in the
+/// floating-origin frame wherever the camera is.
+pub(crate) fn draw_trajectories(
+    floating_origin_settings: Res<FloatingOriginSettings>,
+    mut gizmos: Gizmos,
+    cameras: Query<(&Transform, &GridCell<i64>, &GlobalTransform), With<FloatingOrigin>>,
+    bodies: Query<(&Transform, &GridCell<i64>, Option<&SimulationPosition>)>,
+    predictions: Query<&TrajectoryPrediction>,
+) {
+    let Ok((camera_transform, camera_grid_cell, camera_global_transform)) = cameras.get_single() else {
+        return;
+    };
+    let camera_position = floating_origin_settings.grid_position_double::<i64>(camera_grid_cell, camera_transform);
+    let camera_translation = camera_global_transform.translation();
+    for prediction in predictions.iter() {
+        let Some(Ok((transform, grid_cell, simulation_position))) = prediction.reference.map(|reference| bodies.get(reference)) else {
+            continue;
+        };
+        let offset = absolute_position(&floating_origin_settings, transform, grid_cell, simulation_position) - camera_position;
+        gizmos.linestrip(
+            prediction.points.iter().map(|point| dvec3_to_vec3(offset + *point) + camera_translation),
+            prediction.color,
+        );
+    }
+}
+
+pub(crate) fn spawn_trajectory_labels(
+    predictions: Query<(Entity, &TrajectoryPrediction), Added<TrajectoryPrediction>>,
+    mut cmds: Commands,
+) {
+    for (entity, prediction) in predictions.iter() {
+        for kind in [TrajectoryLabelKind::Periapsis, TrajectoryLabelKind::Apoapsis, TrajectoryLabelKind::Period] {
+            cmds.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 16.0,
+                        color: prediction.color,
+                        ..default()
+                    },
+                )
+                    .with_style(Style {
+                        position_type: PositionType::Absolute,
+                        ..default()
+                    }),
+                TrajectoryLabel {
+                    target: entity,
+                    kind,
+                },
+            ));
+        }
+    }
+}
+
+pub(crate) fn format_distance(meters: f64) -> String {
+    if meters.abs() >= 0.01 * ASTRONOMICAL_UNIT {
+        format!("{:.3} AU", meters / ASTRONOMICAL_UNIT)
+    } else if meters.abs() >= 10_000.0 {
+        format!("{:.0} km", meters / 1000.0)
+    } else {
+        format!("{:.0} m", meters)
+    }
+}
+
+pub(crate) fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m {}s", minutes, seconds % 60)
+    }
+}
+
+/// Moves the labels to the apsides and the entity on screen and updates their text. Labels of
+/// despawned entities are despawned too.
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_trajectory_labels(
+    floating_origin_settings: Res<FloatingOriginSettings>,
+    cameras: Query<(&Camera, &Transform, &GridCell<i64>, &GlobalTransform), With<FloatingOrigin>>,
+    bodies: Query<(&Transform, &GridCell<i64>, Option<&SimulationPosition>)>,
+    predictions: Query<&TrajectoryPrediction>,
+    mut labels: Query<(Entity, &TrajectoryLabel, &mut Text, &mut Style, &mut Visibility)>,
+    mut cmds: Commands,
+) {
+    let Ok((camera, camera_transform, camera_grid_cell, camera_global_transform)) = cameras.get_single() else {
+        return;
+    };
+    let camera_position = floating_origin_settings.grid_position_double::<i64>(camera_grid_cell, camera_transform);
+    let camera_translation = camera_global_transform.translation();
+    let position_of = |entity: Entity| bodies.get(entity).ok()
+        .map(|(transform, grid_cell, simulation_position)| absolute_position(&floating_origin_settings, transform, grid_cell, simulation_position));
+    for (label_entity, label, mut text, mut style, mut visibility) in labels.iter_mut() {
+        let Ok(prediction) = predictions.get(label.target) else {
+            cmds.entity(label_entity).despawn_recursive();
+            continue;
+        };
+        let reference_position = prediction.reference.and_then(position_of);
+        let placement = prediction.orbit.zip(reference_position).and_then(|(orbit, reference_position)| match label.kind {
+            TrajectoryLabelKind::Periapsis => Some((
+                reference_position + orbit.state_at_true_anomaly(0.0).0,
+                format!("Pe {}", format_distance(orbit.periapsis())),
+            )),
+            TrajectoryLabelKind::Apoapsis => orbit.apoapsis().map(|apoapsis| (
+                reference_position + orbit.state_at_true_anomaly(PI).0,
+                format!("Ap {}", format_distance(apoapsis)),
+            )),
+            TrajectoryLabelKind::Period => orbit.period().zip(position_of(label.target)).map(|(period, position)| (
+                position,
+                format!("T {}", format_duration(period)),
+            )),
+        });
+        let screen_position = placement.as_ref().and_then(|(position, _)| {
+            camera.world_to_viewport(camera_global_transform, dvec3_to_vec3(*position - camera_position) + camera_translation)
+        });
+        let (Some((_, label_text)), Some(screen_position)) = (placement, screen_position) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+        style.left = Val::Px(screen_position.x);
+        style.top = Val::Px(screen_position.y);
+        if text.sections[0].value != label_text {
+            text.sections[0].value = label_text;
+        }
+    }
+}
@@ -7,16 +7,16 @@
 use bevy::scene::{SceneBundle, SceneInstance};
 use bevy::utils::default;
 use bevy::utils::tracing::instrument::WithSubscriber;
-use bevy_xpbd_3d::prelude::Position;
 use crate::bevy_stupid::debug_print_components_to_console;
 use crate::gentity::plugin::GEntityBundle;
+use crate::solarsystem::soi::{PatchedConics, SoiTracked};
+use crate::solarsystem::trajectory::TrajectoryPrediction;
 
 pub struct SpaceshipPlugin;
 
 impl Plugin for SpaceshipPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_spaceship)
-            .add_systems(Update, (move_spaceship_along_x_axis));
+        app.add_systems(Startup, setup_spaceship);
     }
 }
 
@@ -41,6 +41,9 @@ pub fn setup_spaceship(
             ..default()
         },
         Spaceship,
+        SoiTracked,
+        PatchedConics,
+        TrajectoryPrediction::new(Color::YELLOW),
     ));
     // commands.spawn((
     //     PbrBundle {
@@ -52,14 +55,6 @@ pub fn setup_spaceship(
     // ));
 }
 
-pub fn move_spaceship_along_x_axis(
-    time: Res<Time>,
-    mut query: Query<&mut Position, With<Spaceship>>
-) {
-    for (mut transform) in query.iter_mut() {
-        transform.z -= time.delta_seconds_f64() * 0.25;
-    }
-}
 pub fn processs_gentity_gltf_scene(
     unloaded_instances: Query<(Entity, &SceneInstance), With<Spaceship>>,
     scene_manager: Res<SceneSpawner>,
@@ -6,6 +6,12 @@
 use clap_complete::Shell;
 use log::{error, info, LevelFilter, trace};
 use simplelog::{ColorChoice, CombinedLogger, Config, TerminalMode, TermLogger, WriteLogger};
+use crate::capabilities::{ScriptCapabilities, ScriptCapability};
+
+// Shared with the game, so the CLI knows exactly the capabilities the game enforces.
+#[path = "../../game/src/gentity/script/capabilities.rs"]
+#[allow(dead_code)]
+mod capabilities;
 
 fn cli_build() -> Command {
     Command::new("build")
@@ -16,9 +22,19 @@ fn cli_build() -> Command {
             .value_hint(ValueHint::FilePath))
 }
 
+fn cli_capabilities() -> Command {
+    Command::new("capabilities")
+        .about("Lists the script capabilities a package declares in its config.toml and the functions they grant")
+        .arg(Arg::new("file")
+            .help("The config.toml of the package")
+            .action(ArgAction::Append)
+            .value_hint(ValueHint::FilePath))
+}
+
 fn cli_app() -> Command {
     Command::new("gentity-cli")
         .subcommand(cli_build())
+        .subcommand(cli_capabilities())
 }
 
 #[derive(Debug)]
@@ -28,6 +44,9 @@ enum CliError {
     BuildExpectedBlendFileFormat,
     BuildFailed(BlendTransformError),
     BuildFilePathError(io::Error),
+    CapabilitiesMissingFileSpec,
+    CapabilitiesReadFailed(io::Error),
+    CapabilitiesInvalidConfig(String),
 }
 
 fn main() -> Result<(), CliError> {
@@ -42,6 +61,9 @@ fn main() -> Result<(), CliError> {
     if let Some(matches) = matches.subcommand_matches("build") {
         build(matches)?;
     }
+    if let Some(matches) = matches.subcommand_matches("capabilities") {
+        capabilities(matches)?;
+    }
 
     Ok(())
 }
@@ -84,6 +106,51 @@ fn build(matches: &ArgMatches) -> Result<(), CliError> {
     Ok(())
 }
 
+/// Reads the `[scripts] capabilities` of a package config, validated the same way the game does.
+fn read_capabilities(config: &str) -> Result<(String, ScriptCapabilities), CliError> {
+    let table = config.parse::<toml::Table>()
+        .map_err(|e| CliError::CapabilitiesInvalidConfig(e.to_string()))?;
+    let identifier = table.get("identifier")
+        .and_then(|identifier| identifier.as_str())
+        .unwrap_or("<no identifier>")
+        .to_string();
+    let Some(declared) = table.get("scripts").and_then(|scripts| scripts.get("capabilities")) else {
+        return Ok((identifier, ScriptCapabilities::default()));
+    };
+    let declared = declared.as_array()
+        .ok_or_else(|| CliError::CapabilitiesInvalidConfig("[scripts] capabilities is not an array".into()))?;
+    let mut capabilities = vec![];
+    for capability in declared {
+        let name = capability.as_str()
+            .ok_or_else(|| CliError::CapabilitiesInvalidConfig(format!("capability {} is not a string", capability)))?;
+        capabilities.push(ScriptCapability::parse(name)
+            .ok_or_else(|| CliError::CapabilitiesInvalidConfig(format!("unknown capability '{}'", name)))?);
+    }
+    Ok((identifier, ScriptCapabilities::new(capabilities)))
+}
+
+fn capabilities(matches: &ArgMatches) -> Result<(), CliError> {
+    let Some(files) = matches.get_many::<String>("file") else {
+        error!("No files specified for capabilities");
+        return Err(CliError::CapabilitiesMissingFileSpec);
+    };
+    for file in files {
+        let config = std::fs::read_to_string(file).map_err(|e| {
+            error!("Failed to read {:?}: {:?}", file, e);
+            CliError::CapabilitiesReadFailed(e)
+        })?;
+        let (identifier, capabilities) = read_capabilities(&config).map_err(|e| {
+            error!("Invalid config {:?}: {:?}", file, e);
+            e
+        })?;
+        println!("{} ({}): {}", identifier, file, capabilities);
+        for capability in capabilities.iter() {
+            println!("  {}: {}", capability, capability.functions().join(", "));
+        }
+    }
+    Ok(())
+}
+
 
 #[test]
 fn verify_cli() {
@@ -117,4 +184,20 @@ fn build_with_multiple_files() {
     let app = cli_app();
     let matches = app.get_matches_from(vec!["gentity-cli", "build", "file1.blend", "file2.blend"]);
     assert_eq!(matches.subcommand_matches("build").unwrap().get_many::<String>("file").unwrap().collect::<Vec<&String>>(), vec!["file1.blend", "file2.blend"]);
-}
\ No newline at end of file
+}
+
+#[test]
+fn capabilities_with_one_file() {
+    let app = cli_app();
+    let matches = app.get_matches_from(vec!["gentity-cli", "capabilities", "config.toml"]);
+    assert_eq!(matches.subcommand_matches("capabilities").unwrap().get_many::<String>("file").unwrap().collect::<Vec<&String>>(), vec!["config.toml"]);
+}
+
+#[test]
+fn read_declared_capabilities() {
+    let (identifier, capabilities) = read_capabilities("identifier = \"Ship\"\n[scripts]\ncapabilities = [\"ui\", \"physics\"]").unwrap();
+    assert_eq!(identifier, "Ship");
+    assert_eq!(capabilities.iter().collect::<Vec<_>>(), vec![ScriptCapability::Physics, ScriptCapability::Ui]);
+    assert!(read_capabilities("identifier = \"Ship\"").unwrap().1.is_empty());
+    assert!(read_capabilities("[scripts]\ncapabilities = [\"teleport\"]").is_err());
+}